@@ -43,6 +43,12 @@ fn main() {
   // This helps Rust source files locate the snapshot, source map etc.
   println!("cargo:rustc-env=GN_OUT_DIR={}", gn_out_dir);
 
+  // Reported by `deno --version` and in bug reports.
+  for path in git_head_files() {
+    println!("cargo:rerun-if-changed={}", path.display());
+  }
+  println!("cargo:rustc-env=DENO_GIT_COMMIT={}", git_commit());
+
   let gn_target;
 
   if check_only {
@@ -97,6 +103,38 @@ fn main() {
   assert!(status.success());
 }
 
+// Returns the commit hash of the checkout being built, or "unknown" when it
+// can't be determined, e.g. when building from a source tarball.
+fn git_commit() -> String {
+  Command::new("git")
+    .args(&["rev-parse", "HEAD"])
+    .output()
+    .ok()
+    .filter(|output| output.status.success())
+    .and_then(|output| String::from_utf8(output.stdout).ok())
+    .map(|hash| hash.trim().to_owned())
+    .filter(|hash| !hash.is_empty())
+    .unwrap_or_else(|| String::from("unknown"))
+}
+
+// The files that change when HEAD moves. HEAD itself only changes when
+// switching branches; committing updates the file of the checked out branch,
+// or packed-refs if the branch was packed. Files that don't exist are left
+// out, as cargo would re-run this script on every build for them.
+fn git_head_files() -> Vec<PathBuf> {
+  let git_dir = Path::new(".git");
+  let head = git_dir.join("HEAD");
+  let mut files = vec![head.clone()];
+  if let Ok(contents) = std::fs::read_to_string(&head) {
+    let contents = contents.trim();
+    if contents.starts_with("ref: ") {
+      files.push(git_dir.join(&contents["ref: ".len()..]));
+    }
+  }
+  files.push(git_dir.join("packed-refs"));
+  files.into_iter().filter(|path| path.exists()).collect()
+}
+
 // Utility function to make a path absolute, normalizing it to use forward
 // slashes only. The returned value is an owned String, otherwise panics.
 fn normalize_path<T: AsRef<Path>>(path: T) -> String {
@@ -1,7 +1,8 @@
 #!/usr/bin/env python
 # Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
-# This file just executes its arguments, except that also adds GN_OUT_DIR and
-# CARGO_PKG_VERSION to the environ. This is for compatibility with cargo.
+# This file just executes its arguments, except that also adds GN_OUT_DIR,
+# CARGO_PKG_VERSION and DENO_GIT_COMMIT to the environ. This is for
+# compatibility with cargo.
 import subprocess
 import sys
 import os
@@ -12,6 +13,16 @@ import re
 os.environ["GN_OUT_DIR"] = os.path.abspath(".")
 assert os.path.isdir(os.environ["GN_OUT_DIR"])
 
+# This is for src/version.rs. When building with Cargo this variable is set by
+# build.rs.
+if "DENO_GIT_COMMIT" not in os.environ:
+    try:
+        commit = subprocess.check_output(["git", "rev-parse", "HEAD"])
+        commit = commit.decode("utf-8").strip()
+    except (OSError, subprocess.CalledProcessError):
+        commit = ""
+    os.environ["DENO_GIT_COMMIT"] = commit or "unknown"
+
 # Set the CARGO_PKG_VERSION env variable if provided as an argument
 # When building with Cargo this variable is set automatically
 args = sys.argv[1:]
@@ -180,6 +180,23 @@ impl StackFrame {
 }
 
 impl JSError {
+  /// Creates a JSError that only carries a message, for exceptions that V8
+  /// could not describe in more detail.
+  pub fn from_message(message: String) -> Self {
+    JSError {
+      message,
+      source_line: None,
+      script_resource_name: None,
+      line_number: None,
+      start_position: None,
+      end_position: None,
+      error_level: None,
+      start_column: None,
+      end_column: None,
+      frames: Vec::new(),
+    }
+  }
+
   /// Creates a new JSError by parsing the raw exception JSON string from V8.
   pub fn from_v8_exception(json_str: &str) -> Option<Self> {
     let v = serde_json::from_str::<serde_json::Value>(json_str);
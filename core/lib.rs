@@ -5,25 +5,37 @@ extern crate libc;
 
 mod js_errors;
 mod libdeno;
+mod modules;
 mod shared;
 
 pub use crate::js_errors::*;
 pub use crate::libdeno::deno_buf;
+pub use crate::libdeno::deno_mod;
 pub use crate::shared::*;
 use futures::Async;
 use futures::Future;
 use futures::Poll;
+use crate::modules::Modules;
+use libc::c_char;
+use libc::c_int;
 use libc::c_void;
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::ffi::CString;
-use std::sync::{Once, ONCE_INIT};
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
 
 pub struct Isolate {
   libdeno_isolate: *const libdeno::isolate,
   pending_ops: HashMap<i32, PendingOp>, // promise_id -> op
   polled_recently: bool,
   recv_cb: RecvCallback,
+  recv_bufs_cb: Option<RecvBufsCallback>,
+  heap_limit_cb: Option<HeapLimitCallback>,
+  terminate_handle: TerminateHandle,
+  modules: Modules,
+  // V8 reads from the snapshot after deno_new(), so it must outlive the
+  // isolate.
+  load_snapshot: Option<Vec<u8>>,
 
   pub shared: Shared,
   pub test_send_counter: u32, // TODO only used for testing- REMOVE.
@@ -31,6 +43,47 @@ pub struct Isolate {
 
 pub type RecvCallback = fn(isolate: &mut Isolate, zero_copy_buf: deno_buf);
 
+/// Called instead of the `RecvCallback` when JavaScript passes an array of
+/// buffers to `libdeno.send()`. Each buffer has its own `zero_copy_id`, which
+/// must be released with `Isolate::zero_copy_release()` or handed to an op.
+pub type RecvBufsCallback =
+  fn(isolate: &mut Isolate, zero_copy_bufs: Vec<deno_buf>);
+
+/// Called when the V8 heap is about to run out. Receives the current and the
+/// initial heap limit in bytes and returns the new limit. Returning the
+/// current limit makes V8 abort the process once the heap is full, unless
+/// execution is terminated with a `TerminateHandle` first.
+pub type HeapLimitCallback = fn(
+  isolate: &mut Isolate,
+  current_heap_limit: usize,
+  initial_heap_limit: usize,
+) -> usize;
+
+/// Lets another thread stop the JavaScript running in an `Isolate`, e.g. a
+/// watchdog that enforces a timeout. It does nothing once the isolate has been
+/// dropped.
+#[derive(Clone)]
+pub struct TerminateHandle {
+  libdeno_isolate: Arc<Mutex<Option<IsolatePtr>>>,
+}
+
+struct IsolatePtr(*const libdeno::isolate);
+
+// deno_terminate_execution() is the only libdeno function that may be called
+// from any thread, and it is the only one TerminateHandle uses.
+unsafe impl Send for IsolatePtr {}
+
+impl TerminateHandle {
+  /// Makes the current (or next) `execute()` call on the isolate fail with an
+  /// "execution terminated" error.
+  pub fn terminate_execution(&self) {
+    let guard = self.libdeno_isolate.lock().unwrap();
+    if let Some(ref ptr) = *guard {
+      unsafe { libdeno::deno_terminate_execution(ptr.0) }
+    }
+  }
+}
+
 pub const NUM_RECORDS: usize = 100;
 
 // TODO rename to AsyncResult
@@ -52,6 +105,54 @@ unsafe impl Send for Isolate {}
 
 impl Isolate {
   pub fn new(recv_cb: RecvCallback) -> Self {
+    Self::new_with_heap_limit(recv_cb, 0, None)
+  }
+
+  /// Creates an isolate whose old space is limited to `heap_limit_mb`
+  /// megabytes (0 keeps V8's default), calling `heap_limit_cb` when the heap
+  /// gets close to the limit.
+  pub fn new_with_heap_limit(
+    recv_cb: RecvCallback,
+    heap_limit_mb: usize,
+    heap_limit_cb: Option<HeapLimitCallback>,
+  ) -> Self {
+    Self::with_config(
+      recv_cb,
+      None,
+      false,
+      None,
+      heap_limit_mb,
+      heap_limit_cb,
+    )
+  }
+
+  /// Creates an isolate that also accepts several zero-copy buffers in a
+  /// single `libdeno.send(control, [buf1, buf2])` call.
+  pub fn new_with_recv_bufs(
+    recv_cb: RecvCallback,
+    recv_bufs_cb: RecvBufsCallback,
+  ) -> Self {
+    Self::with_config(recv_cb, Some(recv_bufs_cb), false, None, 0, None)
+  }
+
+  /// Creates an isolate whose heap can be serialized with `snapshot()`.
+  pub fn new_for_snapshot(recv_cb: RecvCallback) -> Self {
+    Self::with_config(recv_cb, None, true, None, 0, None)
+  }
+
+  /// Creates an isolate from a snapshot produced by `snapshot()`.
+  pub fn from_snapshot(snapshot: Vec<u8>, recv_cb: RecvCallback) -> Self {
+    Self::with_config(recv_cb, None, false, Some(snapshot), 0, None)
+  }
+
+  fn with_config(
+    recv_cb: RecvCallback,
+    recv_bufs_cb: Option<RecvBufsCallback>,
+    will_snapshot: bool,
+    load_snapshot: Option<Vec<u8>>,
+    heap_limit_mb: usize,
+    heap_limit_cb: Option<HeapLimitCallback>,
+  ) -> Self {
     DENO_INIT.call_once(|| {
       unsafe { libdeno::deno_init() };
     });
@@ -61,13 +162,24 @@ impl Isolate {
     let mut shared = Shared::new();
     let shared_deno_buf = shared.as_deno_buf();
 
+    let load_snapshot_buf = match load_snapshot {
+      Some(ref snapshot) => deno_buf::from(snapshot.as_slice()),
+      None => deno_buf::empty(),
+    };
+
     let config = libdeno::deno_config {
-      will_snapshot: 0,
-      load_snapshot: deno_buf::empty(), // TODO
+      will_snapshot: will_snapshot as c_int,
+      load_snapshot: load_snapshot_buf,
       shared: shared_deno_buf,
       recv_cb: pre_dispatch,
+      heap_limit_mb,
+      heap_limit_cb: heap_limit_cb.map(|_| pre_heap_limit as _),
+      recv_bufs_cb: recv_bufs_cb.map(|_| pre_dispatch_bufs as _),
     };
     let libdeno_isolate = unsafe { libdeno::deno_new(config) };
+    let terminate_handle = TerminateHandle {
+      libdeno_isolate: Arc::new(Mutex::new(Some(IsolatePtr(libdeno_isolate)))),
+    };
 
     Self {
       pending_ops: HashMap::new(),
@@ -75,11 +187,37 @@ impl Isolate {
       libdeno_isolate,
       test_send_counter: 0,
       recv_cb,
+      recv_bufs_cb,
+      heap_limit_cb,
+      terminate_handle,
+      modules: Modules::new(),
+      load_snapshot,
       shared,
     }
   }
 
-  fn zero_copy_release(&self, zero_copy_id: usize) {
+  pub fn terminate_handle(&self) -> TerminateHandle {
+    self.terminate_handle.clone()
+  }
+
+  /// Serializes the isolate's heap, so it can be loaded again with
+  /// `Isolate::from_snapshot()`. The isolate must have been created with
+  /// `Isolate::new_for_snapshot()`.
+  pub fn snapshot(self) -> Vec<u8> {
+    unsafe {
+      let buf = libdeno::deno_get_snapshot(self.libdeno_isolate);
+      let snapshot = buf.to_vec();
+      libdeno::deno_snapshot_delete(buf);
+      snapshot
+    }
+  }
+
+  /// Returns V8's current heap usage for this isolate, in bytes.
+  pub fn heap_stats(&self) -> libdeno::deno_heap_stats {
+    unsafe { libdeno::deno_get_heap_stats(self.libdeno_isolate) }
+  }
+
+  pub fn zero_copy_release(&self, zero_copy_id: usize) {
     unsafe {
       libdeno::deno_zero_copy_release(self.libdeno_isolate, zero_copy_id)
     }
@@ -135,15 +273,127 @@ impl Isolate {
     Ok(())
   }
 
+  pub fn mod_new(
+    &mut self,
+    main: bool,
+    name: &str,
+    source: &str,
+  ) -> Result<deno_mod, JSError> {
+    let name_ = CString::new(name).unwrap();
+    let source_ = CString::new(source).unwrap();
+    let id = unsafe {
+      libdeno::deno_mod_new(
+        self.libdeno_isolate,
+        main,
+        name_.as_ptr(),
+        source_.as_ptr(),
+      )
+    };
+    if let Some(js_error) = self.last_exception() {
+      assert_eq!(id, 0);
+      return Err(js_error);
+    }
+    self.modules.register(id, name);
+    Ok(id)
+  }
+
+  /// Returns the import specifiers of module `id`, in source order.
+  pub fn mod_imports(&self, id: deno_mod) -> Vec<String> {
+    let len =
+      unsafe { libdeno::deno_mod_imports_len(self.libdeno_isolate, id) };
+    (0..len)
+      .map(|i| {
+        let specifier_ptr = unsafe {
+          libdeno::deno_mod_imports_get(self.libdeno_isolate, id, i)
+        };
+        let specifier_c = unsafe { CStr::from_ptr(specifier_ptr) };
+        specifier_c.to_string_lossy().into_owned()
+      }).collect()
+  }
+
+  pub fn mod_instantiate(&self, id: deno_mod) -> Result<(), JSError> {
+    unsafe {
+      libdeno::deno_mod_instantiate(
+        self.libdeno_isolate,
+        self.as_raw_ptr(),
+        id,
+        resolve_cb,
+      )
+    };
+    if let Some(js_error) = self.last_exception() {
+      return Err(js_error);
+    }
+    Ok(())
+  }
+
+  pub fn mod_evaluate(&self, id: deno_mod) -> Result<(), JSError> {
+    unsafe {
+      libdeno::deno_mod_evaluate(self.libdeno_isolate, self.as_raw_ptr(), id)
+    };
+    if let Some(js_error) = self.last_exception() {
+      return Err(js_error);
+    }
+    Ok(())
+  }
+
+  /// Loads, instantiates and evaluates the module `specifier` along with
+  /// everything it imports. `loader` is called with each specifier and the
+  /// name of the importing module (`"."` for the main module) and returns
+  /// the resolved module name and its source. Modules are only compiled once
+  /// per resolved name.
+  pub fn load_module<F>(
+    &mut self,
+    specifier: &str,
+    mut loader: F,
+  ) -> Result<deno_mod, JSError>
+  where
+    F: FnMut(&str, &str) -> Result<(String, String), JSError>,
+  {
+    let (name, source) = loader(specifier, ".")?;
+    let id = self.mod_new(true, &name, &source)?;
+    self.mod_load_deps(id, &name, &mut loader)?;
+    self.mod_instantiate(id)?;
+    self.mod_evaluate(id)?;
+    Ok(id)
+  }
+
+  fn mod_load_deps<F>(
+    &mut self,
+    id: deno_mod,
+    referrer_name: &str,
+    loader: &mut F,
+  ) -> Result<(), JSError>
+  where
+    F: FnMut(&str, &str) -> Result<(String, String), JSError>,
+  {
+    for specifier in self.mod_imports(id) {
+      let (name, source) = loader(&specifier, referrer_name)?;
+      let child_id = match self.modules.get_id(&name) {
+        Some(child_id) => child_id,
+        None => {
+          let child_id = self.mod_new(false, &name, &source)?;
+          self.mod_load_deps(child_id, &name, loader)?;
+          child_id
+        }
+      };
+      self.modules.add_resolution(id, &specifier, child_id);
+    }
+    Ok(())
+  }
+
+  /// Returns the exception thrown by the last call into V8, if any. The stack
+  /// and source location are filled in where V8 provided them.
   pub fn last_exception(&self) -> Option<JSError> {
     let ptr = unsafe { libdeno::deno_last_exception(self.libdeno_isolate) };
     if ptr.is_null() {
       None
     } else {
       let cstr = unsafe { CStr::from_ptr(ptr) };
-      let v8_exception = cstr.to_str().unwrap();
+      let v8_exception = cstr.to_string_lossy();
       debug!("v8_exception\n{}\n", v8_exception);
-      let js_error = JSError::from_v8_exception(v8_exception).unwrap();
+      // Don't lose the exception if it isn't the JSON we expect.
+      let js_error = JSError::from_v8_exception(&v8_exception)
+        .unwrap_or_else(|| JSError::from_message(v8_exception.into_owned()));
       Some(js_error)
     }
   }
@@ -156,10 +406,13 @@ impl Isolate {
 
   fn respond(&mut self) -> Result<(), JSError> {
     let buf = deno_buf::empty();
-    unsafe {
+    let rc = unsafe {
       libdeno::deno_respond(self.libdeno_isolate, self.as_raw_ptr(), buf)
-    }
-    if let Some(err) = self.last_exception() {
+    };
+    if rc != 0 {
+      // libdeno always explains why the response wasn't delivered.
+      Err(self.last_exception().unwrap())
+    } else if let Some(err) = self.last_exception() {
       Err(err)
     } else {
       Ok(())
@@ -167,6 +420,14 @@ impl Isolate {
   }
 }
 
+impl Drop for Isolate {
+  fn drop(&mut self) {
+    // Make sure no TerminateHandle uses the isolate after it is deleted.
+    *self.terminate_handle.libdeno_isolate.lock().unwrap() = None;
+    unsafe { libdeno::deno_delete(self.libdeno_isolate) }
+  }
+}
+
 struct LockerScope {
   libdeno_isolate: *const libdeno::isolate,
 }
@@ -276,6 +537,44 @@ extern "C" fn pre_dispatch(
   (isolate.recv_cb)(isolate, zero_copy_buf);
 }
 
+extern "C" fn pre_dispatch_bufs(
+  user_data: *mut c_void,
+  control_buf: deno_buf,
+  zero_copy_bufs: *const deno_buf,
+  zero_copy_bufs_len: usize,
+) {
+  let isolate = unsafe { Isolate::from_raw_ptr(user_data) };
+  assert_eq!(control_buf.len(), 0);
+  // deno_buf isn't Clone. libdeno drops its array after this call, so moving
+  // the buffers out of it is fine.
+  let zero_copy_bufs = (0..zero_copy_bufs_len)
+    .map(|i| unsafe { std::ptr::read(zero_copy_bufs.add(i)) })
+    .collect();
+  let recv_bufs_cb = isolate.recv_bufs_cb.unwrap();
+  recv_bufs_cb(isolate, zero_copy_bufs);
+}
+
+extern "C" fn resolve_cb(
+  user_data: *mut c_void,
+  specifier_ptr: *const c_char,
+  referrer: deno_mod,
+) -> deno_mod {
+  let isolate = unsafe { Isolate::from_raw_ptr(user_data) };
+  let specifier_c = unsafe { CStr::from_ptr(specifier_ptr) };
+  let specifier = specifier_c.to_string_lossy();
+  isolate.modules.resolve(&specifier, referrer)
+}
+
+extern "C" fn pre_heap_limit(
+  user_data: *mut c_void,
+  current_heap_limit: usize,
+  initial_heap_limit: usize,
+) -> usize {
+  let isolate = unsafe { Isolate::from_raw_ptr(user_data) };
+  let heap_limit_cb = isolate.heap_limit_cb.unwrap();
+  heap_limit_cb(isolate, current_heap_limit, initial_heap_limit)
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -308,6 +607,188 @@ mod tests {
     assert_eq!(isolate.test_send_counter, 2);
   }
 
+  #[test]
+  fn test_execute_throws() {
+    let isolate = Isolate::new(inc_counter);
+    js_check(isolate.execute("ok.js", "1 + 1"));
+    let err = isolate
+      .execute("throws.js", "throw Error('boom')")
+      .unwrap_err();
+    assert_eq!(err.message, "Uncaught Error: boom");
+    assert_eq!(err.script_resource_name, Some(String::from("throws.js")));
+    assert_eq!(isolate.test_send_counter, 0);
+  }
+
+  #[test]
+  fn test_last_exception() {
+    let isolate = Isolate::new(inc_counter);
+    assert!(isolate.last_exception().is_none());
+    let err = isolate
+      .execute("reference_error.js", "\n  notDefined();")
+      .unwrap_err();
+    assert_eq!(
+      err.message,
+      "Uncaught ReferenceError: notDefined is not defined"
+    );
+    assert_eq!(err.line_number, Some(2));
+    assert_eq!(err.frames.len(), 1);
+    assert_eq!(err.frames[0].script_name, "reference_error.js");
+  }
+
+  #[test]
+  fn test_terminate_execution() {
+    let isolate = Isolate::new(inc_counter);
+    let terminate_handle = isolate.terminate_handle();
+    let t = std::thread::spawn(move || {
+      std::thread::sleep(std::time::Duration::from_millis(100));
+      terminate_handle.terminate_execution();
+    });
+    let err = isolate
+      .execute("infinite_loop.js", "while (true) {}")
+      .unwrap_err();
+    assert_eq!(err.message, "Uncaught Error: execution terminated");
+    t.join().unwrap();
+  }
+
+  fn near_heap_limit(
+    isolate: &mut Isolate,
+    current_heap_limit: usize,
+    _initial_heap_limit: usize,
+  ) -> usize {
+    isolate.test_send_counter += 1;
+    isolate.terminate_handle().terminate_execution();
+    // Leave enough room for the termination to unwind.
+    current_heap_limit * 2
+  }
+
+  #[test]
+  fn test_heap_limit_callback() {
+    let isolate =
+      Isolate::new_with_heap_limit(inc_counter, 16, Some(near_heap_limit));
+    let err = isolate
+      .execute(
+        "oom.js",
+        r#"
+        const a = [];
+        while (true) {
+          a.push(new Array(1024).fill(0));
+        }
+        "#,
+      ).unwrap_err();
+    assert_eq!(err.message, "Uncaught Error: execution terminated");
+    assert!(isolate.test_send_counter >= 1);
+  }
+
+  #[test]
+  fn test_heap_stats() {
+    let isolate = Isolate::new(inc_counter);
+    let before = isolate.heap_stats();
+    assert!(before.used_heap_size > 0);
+    assert!(before.heap_size_limit >= before.total_heap_size);
+    js_check(isolate.execute(
+      "alloc.js",
+      "var big = new Array(1024 * 1024).fill({});",
+    ));
+    let after = isolate.heap_stats();
+    assert!(after.used_heap_size > before.used_heap_size);
+  }
+
+  #[test]
+  fn test_snapshot() {
+    let isolate = Isolate::new_for_snapshot(inc_counter);
+    js_check(isolate.execute("a.js", "var snapshotted = 42;"));
+    let snapshot = isolate.snapshot();
+    assert!(!snapshot.is_empty());
+
+    let isolate = Isolate::from_snapshot(snapshot, inc_counter);
+    js_check(isolate.execute(
+      "b.js",
+      "if (snapshotted !== 42) throw Error('bad snapshot');",
+    ));
+  }
+
+  fn recv_two_bufs(isolate: &mut Isolate, zero_copy_bufs: Vec<deno_buf>) {
+    assert_eq!(zero_copy_bufs.len(), 2);
+    assert_eq!(&zero_copy_bufs[0][..], &[1, 2]);
+    assert_eq!(&zero_copy_bufs[1][..], &[3, 4, 5]);
+    for buf in zero_copy_bufs {
+      assert!(buf.zero_copy_id > 0);
+      isolate.zero_copy_release(buf.zero_copy_id);
+    }
+    isolate.test_send_counter += 1;
+  }
+
+  #[test]
+  fn test_recv_bufs() {
+    let isolate = Isolate::new_with_recv_bufs(inc_counter, recv_two_bufs);
+    js_check(isolate.execute(
+      "filename.js",
+      r#"
+        const a = new Uint8Array([1, 2]);
+        const b = new Uint8Array([3, 4, 5]);
+        libdeno.send(new Uint8Array(0), [a, b]);
+        libdeno.send();
+        "#,
+    ));
+    assert_eq!(isolate.test_send_counter, 2);
+  }
+
+  #[test]
+  fn test_recv_bufs_without_callback() {
+    let isolate = Isolate::new(inc_counter);
+    let r = isolate.execute(
+      "filename.js",
+      "libdeno.send(new Uint8Array(0), [new Uint8Array(1)]);",
+    );
+    assert!(r.is_err());
+    assert_eq!(isolate.test_send_counter, 0);
+  }
+
+  #[test]
+  fn test_respond_without_recv() {
+    let mut isolate = Isolate::new(inc_counter);
+    let err = isolate.respond().unwrap_err();
+    assert_eq!(err.message, "libdeno.recv_ has not been called.");
+  }
+
+  #[test]
+  fn test_load_module() {
+    let mut isolate = Isolate::new(inc_counter);
+    let mut loads = Vec::new();
+    let id = isolate
+      .load_module("a.js", |specifier, referrer| {
+        loads.push((specifier.to_string(), referrer.to_string()));
+        let source = match specifier {
+          "a.js" => "import { b } from './b.js'; b();",
+          "./b.js" => "export function b() { libdeno.send(); return 'b'; }",
+          _ => return Err(JSError::from_message(format!("no {}", specifier))),
+        };
+        let name = specifier.trim_left_matches("./");
+        Ok((name.to_string(), source.to_string()))
+      }).unwrap();
+    assert!(id > 0);
+    assert_eq!(
+      loads,
+      vec![
+        ("a.js".to_string(), ".".to_string()),
+        ("./b.js".to_string(), "a.js".to_string()),
+      ]
+    );
+    assert_eq!(isolate.test_send_counter, 1);
+  }
+
+  #[test]
+  fn test_load_module_loader_error() {
+    let mut isolate = Isolate::new(inc_counter);
+    let err = isolate
+      .load_module("a.js", |specifier, _| match specifier {
+        "a.js" => Ok(("a.js".to_string(), "import './c.js';".to_string())),
+        _ => Err(JSError::from_message(format!("no {}", specifier))),
+      }).unwrap_err();
+    assert_eq!(err.message, "no ./c.js");
+    assert_eq!(isolate.test_send_counter, 0);
+  }
+
   fn async_immediate(isolate: &mut Isolate, zero_copy_buf: deno_buf) {
     assert_eq!(zero_copy_buf.len(), 0);
     isolate.test_send_counter += 1; // TODO ideally store this in isolate.state?
@@ -0,0 +1,51 @@
+// Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
+use crate::libdeno::deno_mod;
+use std::collections::HashMap;
+
+/// The modules loaded into an `Isolate`, and what each of their import
+/// specifiers resolved to. libdeno asks for the latter when instantiating.
+#[derive(Default)]
+pub struct Modules {
+  by_name: HashMap<String, deno_mod>,
+  // referrer -> specifier -> imported module
+  resolved: HashMap<deno_mod, HashMap<String, deno_mod>>,
+}
+
+impl Modules {
+  pub fn new() -> Modules {
+    Self::default()
+  }
+
+  pub fn get_id(&self, name: &str) -> Option<deno_mod> {
+    self.by_name.get(name).cloned()
+  }
+
+  pub fn register(&mut self, id: deno_mod, name: &str) {
+    debug!("register {}", name);
+    self.by_name.insert(String::from(name), id);
+  }
+
+  pub fn add_resolution(
+    &mut self,
+    referrer: deno_mod,
+    specifier: &str,
+    id: deno_mod,
+  ) {
+    self
+      .resolved
+      .entry(referrer)
+      .or_insert_with(HashMap::new)
+      .insert(String::from(specifier), id);
+  }
+
+  /// Returns 0, which libdeno treats as a failure, if `specifier` was never
+  /// resolved for `referrer`.
+  pub fn resolve(&self, specifier: &str, referrer: deno_mod) -> deno_mod {
+    self
+      .resolved
+      .get(&referrer)
+      .and_then(|specifiers| specifiers.get(specifier))
+      .cloned()
+      .unwrap_or(0)
+  }
+}
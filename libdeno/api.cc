@@ -12,6 +12,21 @@
 #include "exceptions.h"
 #include "internal.h"
 
+namespace deno {
+
+size_t NearHeapLimitCallback(void* data, size_t current_heap_limit,
+                             size_t initial_heap_limit) {
+  auto* d = static_cast<DenoIsolate*>(data);
+  // Only call into the embedder while it is driving the isolate.
+  if (d->user_data_ == nullptr) {
+    return current_heap_limit;
+  }
+  return d->heap_limit_cb_(d->user_data_, current_heap_limit,
+                           initial_heap_limit);
+}
+
+}  // namespace deno
+
 extern "C" {
 
 Deno* deno_new_snapshotter(deno_config config) {
@@ -50,9 +65,15 @@ Deno* deno_new(deno_config config) {
   if (config.load_snapshot.data_ptr) {
     params.snapshot_blob = &d->snapshot_;
   }
+  if (config.heap_limit_mb > 0) {
+    params.constraints.set_max_old_space_size(config.heap_limit_mb);
+  }
 
   v8::Isolate* isolate = v8::Isolate::New(params);
   d->AddIsolate(isolate);
+  if (config.heap_limit_cb) {
+    isolate->AddNearHeapLimitCallback(deno::NearHeapLimitCallback, d);
+  }
 
   v8::Locker locker(isolate);
   v8::Isolate::Scope isolate_scope(isolate);
@@ -103,6 +124,8 @@ deno_buf deno_get_snapshot(Deno* d_) {
           blob.raw_size, 0};
 }
 
+void deno_snapshot_delete(deno_buf snapshot) { delete[] snapshot.data_ptr; }
+
 static std::unique_ptr<v8::Platform> platform;
 
 void deno_init() {
@@ -148,7 +171,7 @@ void deno_zero_copy_release(Deno* d_, size_t zero_copy_id) {
   d->DeleteZeroCopyRef(zero_copy_id);
 }
 
-void deno_respond(Deno* d_, void* user_data, deno_buf buf) {
+int deno_respond(Deno* d_, void* user_data, deno_buf buf) {
   auto* d = unwrap(d_);
   if (d->current_args_ != nullptr) {
     // Synchronous response.
@@ -158,7 +181,7 @@ void deno_respond(Deno* d_, void* user_data, deno_buf buf) {
       d->current_args_->GetReturnValue().Set(ab);
     }
     d->current_args_ = nullptr;
-    return;
+    return 0;
   }
 
   // Asynchronous response.
@@ -175,7 +198,7 @@ void deno_respond(Deno* d_, void* user_data, deno_buf buf) {
   auto recv_ = d->recv_.Get(d->isolate_);
   if (recv_.IsEmpty()) {
     d->last_exception_ = "libdeno.recv_ has not been called.";
-    return;
+    return -1;
   }
 
   v8::Local<v8::Value> args[1];
@@ -193,8 +216,13 @@ void deno_respond(Deno* d_, void* user_data, deno_buf buf) {
 
   if (try_catch.HasCaught()) {
     CHECK(v.IsEmpty());
+    if (try_catch.HasTerminated()) {
+      deno::HandleTermination(context);
+      return -1;
+    }
     deno::HandleException(context, try_catch.Exception());
   }
+  return 0;
 }
 
 void deno_check_promise_errors(Deno* d_) {
@@ -225,4 +253,18 @@ void deno_terminate_execution(Deno* d_) {
   deno::DenoIsolate* d = reinterpret_cast<deno::DenoIsolate*>(d_);
   d->isolate_->TerminateExecution();
 }
+
+deno_heap_stats deno_get_heap_stats(Deno* d_) {
+  auto* d = unwrap(d_);
+  auto* isolate = d->isolate_;
+  v8::Locker locker(isolate);
+  v8::HeapStatistics v8_stats;
+  isolate->GetHeapStatistics(&v8_stats);
+  deno_heap_stats stats;
+  stats.total_heap_size = v8_stats.total_heap_size();
+  stats.used_heap_size = v8_stats.used_heap_size();
+  stats.heap_size_limit = v8_stats.heap_size_limit();
+  stats.external_memory = v8_stats.external_memory();
+  return stats;
+}
 }
@@ -215,8 +215,39 @@ void Send(const v8::FunctionCallbackInfo<v8::Value>& args) {
     }
   }
 
+  std::vector<deno_buf> zero_copy_bufs;
+  bool send_bufs = args.Length() == 2 && args[1]->IsArray();
+  if (send_bufs) {
+    if (d->recv_bufs_cb_ == nullptr) {
+      isolate->ThrowException(
+          v8_str("libdeno.send() does not accept an array of buffers."));
+      return;
+    }
+    auto context = d->context_.Get(isolate);
+    auto zero_copy_a = v8::Local<v8::Array>::Cast(args[1]);
+    for (uint32_t i = 0; i < zero_copy_a->Length(); i++) {
+      auto zero_copy_v = zero_copy_a->Get(context, i).ToLocalChecked();
+      if (!zero_copy_v->IsArrayBufferView()) {
+        isolate->ThrowException(
+            v8_str("libdeno.send() buffers must be ArrayBufferViews."));
+        return;
+      }
+      zero_copy_bufs.push_back(GetContents(
+          isolate, v8::Local<v8::ArrayBufferView>::Cast(zero_copy_v)));
+    }
+    // Only take references once every element is known to be valid, so a
+    // thrown exception doesn't leak any of them.
+    for (uint32_t i = 0; i < zero_copy_bufs.size(); i++) {
+      size_t zero_copy_id = d->next_zero_copy_id_++;
+      DCHECK_GT(zero_copy_id, 0);
+      zero_copy_bufs[i].zero_copy_id = zero_copy_id;
+      d->AddZeroCopyRef(zero_copy_id,
+                        zero_copy_a->Get(context, i).ToLocalChecked());
+    }
+  }
+
   v8::Local<v8::Value> zero_copy_v;
-  if (args.Length() == 2) {
+  if (args.Length() == 2 && !send_bufs) {
     if (args[1]->IsArrayBufferView()) {
       zero_copy_v = args[1];
       zero_copy = GetContents(
@@ -233,7 +264,12 @@ void Send(const v8::FunctionCallbackInfo<v8::Value>& args) {
   DCHECK_NULL(d->current_args_);
   d->current_args_ = &args;
 
-  d->recv_cb_(d->user_data_, control, zero_copy);
+  if (send_bufs) {
+    d->recv_bufs_cb_(d->user_data_, control, zero_copy_bufs.data(),
+                     zero_copy_bufs.size());
+  } else {
+    d->recv_cb_(d->user_data_, control, zero_copy);
+  }
 
   if (d->current_args_ == nullptr) {
     // This indicates that deno_repond() was called already.
@@ -368,7 +404,11 @@ bool Execute(v8::Local<v8::Context> context, const char* js_filename,
 
   if (result.IsEmpty()) {
     DCHECK(try_catch.HasCaught());
-    HandleException(context, try_catch.Exception());
+    if (try_catch.HasTerminated()) {
+      HandleTermination(context);
+    } else {
+      HandleException(context, try_catch.Exception());
+    }
     return false;
   }
 
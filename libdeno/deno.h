@@ -26,15 +26,36 @@ typedef struct deno_s Deno;
 typedef void (*deno_recv_cb)(void* user_data, deno_buf control_buf,
                              deno_buf zerop_copy_buf);
 
+// Like deno_recv_cb, but called when libdeno.send() is given an array of
+// zero-copy buffers. zero_copy_bufs is valid only for the lifetime of this
+// callback, while each buffer in it is valid until deno_zero_copy_release() is
+// called with its zero_copy_id.
+typedef void (*deno_recv_bufs_cb)(void* user_data, deno_buf control_buf,
+                                  const deno_buf* zero_copy_bufs,
+                                  size_t zero_copy_bufs_len);
+
 void deno_init();
 const char* deno_v8_version();
 void deno_set_v8_flags(int* argc, char** argv);
 
+// A callback invoked when V8's heap is close to its limit. It returns the new
+// heap limit in bytes. If it returns current_heap_limit unchanged, V8 aborts
+// the process once the heap is full, unless execution is terminated first
+// with deno_terminate_execution().
+typedef size_t (*deno_heap_limit_cb)(void* user_data,
+                                     size_t current_heap_limit,
+                                     size_t initial_heap_limit);
+
 typedef struct {
   int will_snapshot;       // Default 0. If calling deno_get_snapshot 1.
   deno_buf load_snapshot;  // Optionally: A deno_buf from deno_get_snapshot.
   deno_buf shared;         // Shared buffer to be mapped to libdeno.shared
   deno_recv_cb recv_cb;    // Maps to libdeno.send() calls.
+  // The two fields below are ignored if will_snapshot is set.
+  size_t heap_limit_mb;              // Default 0. Max old space size in MB.
+  deno_heap_limit_cb heap_limit_cb;  // Optional.
+  // Optional. Without it, passing an array to libdeno.send() throws.
+  deno_recv_bufs_cb recv_bufs_cb;
 } deno_config;
 
 // Create a new deno isolate.
@@ -43,9 +64,12 @@ typedef struct {
 Deno* deno_new(deno_config config);
 
 // Generate a snapshot. The resulting buf can be used with deno_new.
-// The caller must free the returned data by calling delete[] buf.data_ptr.
+// The caller must free the returned data by calling delete[] buf.data_ptr,
+// or deno_snapshot_delete() from languages that can't.
 deno_buf deno_get_snapshot(Deno* d);
 
+void deno_snapshot_delete(deno_buf snapshot);
+
 void deno_delete(Deno* d);
 
 void deno_lock(Deno* d);
@@ -71,7 +95,11 @@ void deno_execute(Deno* d, void* user_data, const char* js_filename,
 // releasing its memory.)
 //
 // If a JS exception was encountered, deno_last_exception() will be non-NULL.
-void deno_respond(Deno* d, void* user_data, deno_buf buf);
+//
+// Returns 0 if buf was handed to JavaScript. Returns -1 if it could not be
+// delivered, because libdeno.recv() was never called or execution was
+// terminated; deno_last_exception() then describes why.
+int deno_respond(Deno* d, void* user_data, deno_buf buf);
 
 // consumes zero_copy
 // Calling this function more than once with the same zero_copy_id will result
@@ -82,8 +110,20 @@ void deno_check_promise_errors(Deno* d);
 
 const char* deno_last_exception(Deno* d);
 
+// Stops any JavaScript running in the isolate as soon as possible. Unlike the
+// rest of this API, this may be called from any thread. The interrupted call
+// fails with an "execution terminated" exception.
 void deno_terminate_execution(Deno* d);
 
+typedef struct {
+  size_t total_heap_size;
+  size_t used_heap_size;
+  size_t heap_size_limit;
+  size_t external_memory;
+} deno_heap_stats;
+
+deno_heap_stats deno_get_heap_stats(Deno* d);
+
 // Module API
 
 typedef int deno_mod;
@@ -188,4 +188,13 @@ void HandleExceptionMessage(v8::Local<v8::Context> context,
   CHECK_NOT_NULL(d);
   d->last_exception_ = json_str;
 }
+
+void HandleTermination(v8::Local<v8::Context> context) {
+  v8::Isolate* isolate = context->GetIsolate();
+  DenoIsolate* d = DenoIsolate::FromIsolate(isolate);
+  CHECK_NOT_NULL(d);
+  isolate->CancelTerminateExecution();
+  d->last_exception_ =
+      "{\"message\":\"Uncaught Error: execution terminated\",\"frames\":[]}";
+}
 }  // namespace deno
@@ -18,6 +18,10 @@ void HandleException(v8::Local<v8::Context> context,
 
 void HandleExceptionMessage(v8::Local<v8::Context> context,
                             v8::Local<v8::Message> message);
+
+// Records that execution was stopped by deno_terminate_execution() and lets
+// the isolate run scripts again.
+void HandleTermination(v8::Local<v8::Context> context);
 }  // namespace deno
 
 #endif  // EXCEPTIONS_H_
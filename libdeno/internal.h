@@ -36,6 +36,8 @@ class DenoIsolate {
         snapshot_creator_(nullptr),
         global_import_buf_ptr_(nullptr),
         recv_cb_(config.recv_cb),
+        recv_bufs_cb_(config.recv_bufs_cb),
+        heap_limit_cb_(config.heap_limit_cb),
         next_zero_copy_id_(1),  // zero_copy_id must not be zero.
         user_data_(nullptr),
         resolve_cb_(nullptr) {
@@ -106,6 +108,8 @@ class DenoIsolate {
   v8::SnapshotCreator* snapshot_creator_;
   void* global_import_buf_ptr_;
   deno_recv_cb recv_cb_;
+  deno_recv_bufs_cb recv_bufs_cb_;
+  deno_heap_limit_cb heap_limit_cb_;
   size_t next_zero_copy_id_;
   void* user_data_;
 
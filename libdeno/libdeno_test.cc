@@ -110,6 +110,13 @@ TEST(LibDenoTest, RecvReturnBar) {
   deno_delete(d);
 }
 
+TEST(LibDenoTest, RespondWithoutRecvFails) {
+  Deno* d = deno_new(deno_config{0, snapshot, empty, nullptr});
+  EXPECT_EQ(-1, deno_respond(d, nullptr, strbuf("bar")));
+  EXPECT_NE(nullptr, deno_last_exception(d));
+  deno_delete(d);
+}
+
 TEST(LibDenoTest, DoubleRecvFails) {
   Deno* d = deno_new(deno_config{0, snapshot, empty, nullptr});
   deno_execute(d, nullptr, "a.js", "DoubleRecvFails()");
@@ -232,6 +239,43 @@ TEST(LibDenoTest, ZeroCopyBuf) {
   deno_delete(d);
 }
 
+TEST(LibDenoTest, ZeroCopyBufs) {
+  static int count = 0;
+  auto recv_cb = [](auto _, auto buf, auto zero_copy_buf) { FAIL(); };
+  auto recv_bufs_cb = [](auto user_data, deno_buf buf,
+                         const deno_buf* zero_copy_bufs,
+                         size_t zero_copy_bufs_len) {
+    count++;
+    EXPECT_EQ(1u, buf.data_len);
+    EXPECT_EQ(2u, zero_copy_bufs_len);
+    EXPECT_EQ(2u, zero_copy_bufs[0].data_len);
+    EXPECT_EQ(zero_copy_bufs[0].data_ptr[0], 3);
+    EXPECT_EQ(3u, zero_copy_bufs[1].data_len);
+    EXPECT_EQ(zero_copy_bufs[1].data_ptr[2], 7);
+    EXPECT_NE(zero_copy_bufs[0].zero_copy_id, zero_copy_bufs[1].zero_copy_id);
+    auto d = reinterpret_cast<Deno*>(user_data);
+    for (size_t i = 0; i < zero_copy_bufs_len; i++) {
+      EXPECT_GT(zero_copy_bufs[i].zero_copy_id, 0u);
+      deno_zero_copy_release(d, zero_copy_bufs[i].zero_copy_id);
+    }
+  };
+  deno_config config{0, snapshot, empty, recv_cb};
+  config.recv_bufs_cb = recv_bufs_cb;
+  Deno* d = deno_new(config);
+  deno_execute(d, d, "a.js", "ZeroCopyBufs()");
+  EXPECT_EQ(nullptr, deno_last_exception(d));
+  EXPECT_EQ(count, 1);
+  deno_delete(d);
+}
+
+TEST(LibDenoTest, ZeroCopyBufsWithoutCallback) {
+  auto recv_cb = [](auto _, auto buf, auto zero_copy_buf) { FAIL(); };
+  Deno* d = deno_new(deno_config{0, snapshot, empty, recv_cb});
+  deno_execute(d, d, "a.js", "ZeroCopyBufs()");
+  EXPECT_NE(nullptr, deno_last_exception(d));
+  deno_delete(d);
+}
+
 TEST(LibDenoTest, CheckPromiseErrors) {
   static int count = 0;
   auto recv_cb = [](auto _, auto buf, auto zero_copy_buf) { count++; };
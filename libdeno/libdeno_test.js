@@ -120,6 +120,14 @@ global.ZeroCopyBuf = () => {
   b[1] = 8;
 };
 
+global.ZeroCopyBufs = () => {
+  const control = new Uint8Array([1]);
+  const head = new Uint8Array([3, 4]);
+  const body = new Uint8Array([5, 6, 7]);
+  const r = libdeno.send(control, [head, body]);
+  assert(r == null);
+};
+
 global.CheckPromiseErrors = () => {
   async function fn() {
     throw new Error("message");
@@ -1,5 +1,7 @@
 // Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
-use crate::resources::{EagerAccept, EagerRead, EagerWrite, Resource};
+use crate::resources::{
+  Accepted, EagerAccept, EagerRead, EagerWrite, Resource,
+};
 use crate::tokio_util;
 use crate::tokio_write;
 
@@ -34,6 +36,30 @@ pub fn tcp_read<T: AsMut<[u8]>>(
   }
 }
 
+// tokio_process pipes are already non-blocking, so like tcp_read() we can
+// attempt a read() on the raw fd and only defer to tokio on EWOULDBLOCK.
+pub fn child_read<R: AsRawFd, T: AsMut<[u8]>>(
+  child_pipe: &R,
+  resource: Resource,
+  mut buf: T,
+) -> EagerRead<Resource, T> {
+  let fd = child_pipe.as_raw_fd();
+  let mut std_file = unsafe { std::fs::File::from_raw_fd(fd) };
+  let read_result = std_file.read(buf.as_mut());
+  // std_file will close when it gets dropped. Thus...
+  let _ = std_file.into_raw_fd();
+  match read_result {
+    Ok(nread) => Either::B(future::ok((resource, buf, nread))),
+    Err(err) => {
+      if err.kind() == ErrorKind::WouldBlock {
+        Either::A(tokio_io::io::read(resource, buf))
+      } else {
+        Either::B(future::err(err))
+      }
+    }
+  }
+}
+
 pub fn tcp_write<T: AsRef<[u8]>>(
   tcp_stream: &TcpStream,
   resource: Resource,
@@ -56,6 +82,22 @@ pub fn tcp_write<T: AsRef<[u8]>>(
   }
 }
 
+// This is a blocking write() on the calling thread, which for eager_write()
+// is the event loop. Regular files never report EWOULDBLOCK, so there is no
+// fallback to tokio: a slow disk or an NFS mount stalls the event loop until
+// the write returns.
+pub fn fs_write<T: AsRef<[u8]>>(
+  std_file: &std::fs::File,
+  resource: Resource,
+  buf: T,
+) -> EagerWrite<Resource, T> {
+  let mut std_file = std_file;
+  let result = std_file.write(buf.as_ref());
+  Either::B(future::result(
+    result.map(move |nwrite| (resource, buf, nwrite)),
+  ))
+}
+
 pub fn tcp_accept(
   tcp_listener: &TcpListener,
   resource: Resource,
@@ -72,7 +114,7 @@ pub fn tcp_accept(
         &tokio::reactor::Handle::default(),
       );
       let tokio_stream = result.unwrap();
-      Either::B(future::ok((tokio_stream, addr)))
+      Either::B(future::ok(Accepted::Tcp(tokio_stream, addr)))
     }
     Err(err) => {
       if err.kind() == ErrorKind::WouldBlock {
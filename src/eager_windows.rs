@@ -0,0 +1,60 @@
+// Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
+use crate::resources::{EagerRead, EagerWrite, Resource};
+use crate::tokio_write;
+
+use futures::future::{self, Either};
+use std;
+use std::io::{ErrorKind, Read, Write};
+use std::os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket};
+use tokio::net::TcpStream;
+use tokio_io;
+
+// mio only posts zero-byte overlapped reads to wait for readiness and leaves
+// the socket in non-blocking mode, so calling recv() directly does not race
+// with any buffered data on its side.
+pub fn tcp_read<T: AsMut<[u8]>>(
+  tcp_stream: &TcpStream,
+  resource: Resource,
+  mut buf: T,
+) -> EagerRead<Resource, T> {
+  // Unfortunately we can't just call read() on tokio::net::TcpStream
+  let socket = (*tcp_stream).as_raw_socket();
+  let mut std_tcp_stream =
+    unsafe { std::net::TcpStream::from_raw_socket(socket) };
+  let read_result = std_tcp_stream.read(buf.as_mut());
+  // std_tcp_stream will close when it gets dropped. Thus...
+  let _ = std_tcp_stream.into_raw_socket();
+  match read_result {
+    Ok(nread) => Either::B(future::ok((resource, buf, nread))),
+    Err(err) => {
+      if err.kind() == ErrorKind::WouldBlock {
+        Either::A(tokio_io::io::read(resource, buf))
+      } else {
+        Either::B(future::err(err))
+      }
+    }
+  }
+}
+
+pub fn tcp_write<T: AsRef<[u8]>>(
+  tcp_stream: &TcpStream,
+  resource: Resource,
+  buf: T,
+) -> EagerWrite<Resource, T> {
+  let socket = (*tcp_stream).as_raw_socket();
+  let mut std_tcp_stream =
+    unsafe { std::net::TcpStream::from_raw_socket(socket) };
+  let write_result = std_tcp_stream.write(buf.as_ref());
+  // std_tcp_stream will close when it gets dropped. Thus...
+  let _ = std_tcp_stream.into_raw_socket();
+  match write_result {
+    Ok(nwrite) => Either::B(future::ok((resource, buf, nwrite))),
+    Err(err) => {
+      if err.kind() == ErrorKind::WouldBlock {
+        Either::A(tokio_write::write(resource, buf))
+      } else {
+        Either::B(future::err(err))
+      }
+    }
+  }
+}
@@ -56,7 +56,8 @@ impl DenoError {
           WriteZero => ErrorKind::WriteZero,
           Other => ErrorKind::Other,
           UnexpectedEof => ErrorKind::UnexpectedEof,
-          _ => unreachable!(),
+          // io::ErrorKind is non-exhaustive.
+          _ => ErrorKind::Other,
         }
       }
       Repr::UrlErr(ref err) => {
@@ -92,6 +93,16 @@ impl DenoError {
       }
     }
   }
+
+  /// The kind of the underlying io::Error, if this error came from one. It
+  /// can tell apart errors that `kind()` maps to `Other`, and lets callers
+  /// decide whether an op is worth retrying.
+  pub fn io_kind(&self) -> Option<io::ErrorKind> {
+    match self.repr {
+      Repr::IoErr(ref err) => Some(err.kind()),
+      Repr::Simple(..) | Repr::UrlErr(_) | Repr::HyperErr(_) => None,
+    }
+  }
 }
 
 impl fmt::Display for DenoError {
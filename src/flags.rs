@@ -336,7 +336,12 @@ pub fn v8_set_flags(args: Vec<String>) -> Vec<String> {
   // flags that v8 understands.
   // First parse core args, then convert to a vector of C strings.
   let (args, rest) = v8_set_flags_preprocess(args);
+  deno_set_v8_flags(args).into_iter().chain(rest.into_iter()).collect()
+}
 
+// Calls deno_set_v8_flags() with `args` (including argv[0]) and returns the
+// ones v8 left in place.
+fn deno_set_v8_flags(args: Vec<String>) -> Vec<String> {
   // Make a new array, that can be modified by V8::SetFlagsFromCommandLine(),
   // containing mutable raw pointers to the individual command line args.
   let mut raw_argv = args
@@ -363,8 +368,90 @@ pub fn v8_set_flags(args: Vec<String>) -> Vec<String> {
     .iter()
     .map(|ptr| unsafe {
       let cstr = CStr::from_ptr(*ptr as *const c_char);
-      let slice = cstr.to_str().unwrap();
-      slice.to_string()
-    }).chain(rest.into_iter())
-    .collect()
+      cstr.to_string_lossy().into_owned()
+    }).collect()
+}
+
+/// Builds a set of V8 flags, for embedders that configure V8 directly rather
+/// than through the command line.
+///
+/// ```ignore
+/// let rejected = V8Flags::new().max_old_space_size(512).apply();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct V8Flags {
+  flags: Vec<String>,
+}
+
+impl V8Flags {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the size of the old generation heap, in megabytes.
+  pub fn max_old_space_size(&mut self, mb: usize) -> &mut Self {
+    self.raw(&format!("--max-old-space-size={}", mb))
+  }
+
+  /// Exposes `gc()` to JavaScript.
+  pub fn expose_gc(&mut self, enabled: bool) -> &mut Self {
+    self.bool_flag("expose-gc", enabled)
+  }
+
+  /// Enables the harmony features that are still in progress.
+  pub fn harmony(&mut self, enabled: bool) -> &mut Self {
+    self.bool_flag("harmony", enabled)
+  }
+
+  /// Adds a flag as is, e.g. "--stack-size=2000".
+  pub fn raw(&mut self, flag: &str) -> &mut Self {
+    self.flags.push(flag.to_string());
+    self
+  }
+
+  fn bool_flag(&mut self, name: &str, enabled: bool) -> &mut Self {
+    let flag = if enabled {
+      format!("--{}", name)
+    } else {
+      format!("--no-{}", name)
+    };
+    self.raw(&flag)
+  }
+
+  /// The argv handed to v8. argv[0] is a placeholder program name, since v8
+  /// skips it.
+  pub fn to_argv(&self) -> Vec<String> {
+    let mut argv = vec!["deno".to_string()];
+    argv.extend(self.flags.iter().cloned());
+    argv
+  }
+
+  /// Passes the flags to v8 and returns the ones it didn't understand.
+  pub fn apply(&self) -> Vec<String> {
+    let mut rejected = deno_set_v8_flags(self.to_argv());
+    // v8 always leaves argv[0] in place.
+    rejected.remove(0);
+    rejected
+  }
+}
+
+#[test]
+fn test_v8_flags_to_argv() {
+  let argv = V8Flags::new()
+    .max_old_space_size(512)
+    .expose_gc(true)
+    .harmony(false)
+    .raw("--stack-size=2000")
+    .to_argv();
+  assert_eq!(
+    argv,
+    svec![
+      "deno",
+      "--max-old-space-size=512",
+      "--expose-gc",
+      "--no-harmony",
+      "--stack-size=2000"
+    ]
+  );
+  assert_eq!(V8Flags::new().to_argv(), svec!["deno"]);
 }
@@ -0,0 +1,227 @@
+// Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
+use futures::Async;
+use futures::Poll;
+use libc::c_int;
+use mio::unix::EventedFd;
+use mio::{Evented, PollOpt, Ready, Token};
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::reactor::PollEvented2;
+
+const WATCH_MASK: u32 = libc::IN_CREATE
+  | libc::IN_MOVED_TO
+  | libc::IN_MODIFY
+  | libc::IN_ATTRIB
+  | libc::IN_DELETE
+  | libc::IN_MOVED_FROM
+  | libc::IN_DELETE_SELF;
+
+const EVENT_HEADER_SIZE: usize = mem::size_of::<libc::inotify_event>();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FsEventKind {
+  Create,
+  Modify,
+  Remove,
+  Other,
+}
+
+impl FsEventKind {
+  fn from_mask(mask: u32) -> Self {
+    if mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+      FsEventKind::Create
+    } else if mask & (libc::IN_MODIFY | libc::IN_ATTRIB) != 0 {
+      FsEventKind::Modify
+    } else if mask
+      & (libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_DELETE_SELF)
+      != 0
+    {
+      FsEventKind::Remove
+    } else {
+      FsEventKind::Other
+    }
+  }
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      FsEventKind::Create => "create",
+      FsEventKind::Modify => "modify",
+      FsEventKind::Remove => "remove",
+      FsEventKind::Other => "other",
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FsEvent {
+  pub path: PathBuf,
+  pub kind: FsEventKind,
+}
+
+// Owns the inotify file descriptor.
+struct Inotify(c_int);
+
+impl Drop for Inotify {
+  fn drop(&mut self) {
+    unsafe { libc::close(self.0) };
+  }
+}
+
+impl Evented for Inotify {
+  fn register(
+    &self,
+    poll: &mio::Poll,
+    token: Token,
+    interest: Ready,
+    opts: PollOpt,
+  ) -> io::Result<()> {
+    EventedFd(&self.0).register(poll, token, interest, opts)
+  }
+
+  fn reregister(
+    &self,
+    poll: &mio::Poll,
+    token: Token,
+    interest: Ready,
+    opts: PollOpt,
+  ) -> io::Result<()> {
+    EventedFd(&self.0).reregister(poll, token, interest, opts)
+  }
+
+  fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
+    EventedFd(&self.0).deregister(poll)
+  }
+}
+
+/// Watches files and directories for changes using inotify. Dropping the
+/// watcher stops the watch.
+pub struct FsWatcher {
+  inotify: PollEvented2<Inotify>,
+  recursive: bool,
+  // watch descriptor -> watched path
+  watches: HashMap<c_int, PathBuf>,
+  events: VecDeque<FsEvent>,
+}
+
+impl FsWatcher {
+  /// Starts watching `paths`. With `recursive`, the subdirectories of the
+  /// given directories are watched too, including ones created later.
+  pub fn new(paths: &[PathBuf], recursive: bool) -> io::Result<FsWatcher> {
+    let fd =
+      unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if fd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    let mut watcher = FsWatcher {
+      inotify: PollEvented2::new(Inotify(fd)),
+      recursive,
+      watches: HashMap::new(),
+      events: VecDeque::new(),
+    };
+    for path in paths {
+      watcher.add_watch(path)?;
+    }
+    Ok(watcher)
+  }
+
+  fn add_watch(&mut self, path: &Path) -> io::Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let fd = self.inotify.get_ref().0;
+    let wd =
+      unsafe { libc::inotify_add_watch(fd, c_path.as_ptr(), WATCH_MASK) };
+    if wd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    self.watches.insert(wd, path.to_path_buf());
+    if self.recursive && path.is_dir() {
+      for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+          self.add_watch(&entry.path())?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Resolves with the next change to a watched path.
+  pub fn poll_event(&mut self) -> Poll<FsEvent, io::Error> {
+    loop {
+      if let Some(event) = self.events.pop_front() {
+        return Ok(Async::Ready(event));
+      }
+      try_ready!(self.inotify.poll_read_ready(Ready::readable()));
+      self.read_events()?;
+    }
+  }
+
+  fn read_events(&mut self) -> io::Result<()> {
+    // Large enough for at least one event with the longest possible name.
+    let mut buf = [0u8; 4096];
+    let fd = self.inotify.get_ref().0;
+    let n = unsafe {
+      libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+    };
+    if n < 0 {
+      let err = io::Error::last_os_error();
+      if err.kind() == io::ErrorKind::WouldBlock {
+        self.inotify.clear_read_ready(Ready::readable())?;
+        return Ok(());
+      }
+      return Err(err);
+    }
+
+    let n = n as usize;
+    let mut offset = 0;
+    while offset + EVENT_HEADER_SIZE <= n {
+      // buf is only byte aligned.
+      let header: libc::inotify_event =
+        unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr() as *const _) };
+      let name_start = offset + EVENT_HEADER_SIZE;
+      let name_end = name_start + header.len as usize;
+      offset = name_end;
+
+      let dir = match self.watches.get(&header.wd) {
+        Some(dir) => dir.clone(),
+        // Events for a watch that was just removed.
+        None => continue,
+      };
+      // The name is padded with NUL bytes.
+      let name = &buf[name_start..name_end];
+      let name = match name.iter().position(|&b| b == 0) {
+        Some(len) => &name[..len],
+        None => name,
+      };
+      let path = if name.is_empty() {
+        dir
+      } else {
+        dir.join(OsStr::from_bytes(name))
+      };
+
+      if header.mask & libc::IN_IGNORED != 0 {
+        self.watches.remove(&header.wd);
+        continue;
+      }
+      let is_new_dir = header.mask & libc::IN_ISDIR != 0
+        && header.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0;
+      if self.recursive && is_new_dir {
+        // The directory may already be gone again; that's not an error.
+        let _ = self.add_watch(&path);
+      }
+      self.events.push_back(FsEvent {
+        path,
+        kind: FsEventKind::from_mask(header.mask),
+      });
+    }
+    Ok(())
+  }
+}
@@ -5,6 +5,7 @@ use futures::Poll;
 use hyper::body::Payload;
 use hyper::Body;
 use hyper::Chunk;
+use hyper::HeaderMap;
 use std::cmp::min;
 use std::io;
 use std::io::Read;
@@ -16,6 +17,7 @@ pub struct HttpBody {
   body: Body,
   chunk: Option<Chunk>,
   pos: usize,
+  capacity_hint: Option<usize>,
 }
 
 impl HttpBody {
@@ -24,13 +26,44 @@ impl HttpBody {
       body,
       chunk: None,
       pos: 0,
+      capacity_hint: None,
     }
   }
+
+  /// Tells readers how many bytes to expect, so that they can allocate
+  /// up front. Overrides the declared length.
+  pub fn hint_capacity(&mut self, bytes: usize) {
+    self.capacity_hint = Some(bytes);
+  }
+
+  /// The number of bytes readers should expect: the hint given to
+  /// `hint_capacity()`, or else the declared length, or else 0.
+  pub fn capacity_hint(&self) -> usize {
+    self
+      .capacity_hint
+      .or_else(|| self.content_length().map(|len| len as usize))
+      .unwrap_or(0)
+  }
+
+  /// The length of the body as declared by the sender, if known.
+  pub fn content_length(&self) -> Option<u64> {
+    self.body.content_length()
+  }
+
+  /// Polls for the trailer headers. Only meaningful once all of the body has
+  /// been read; resolves to None if there are no trailers.
+  pub fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, hyper::Error> {
+    self.body.poll_trailers()
+  }
 }
 
+// Only AsyncRead is supported. The body can't be polled outside of a task.
 impl Read for HttpBody {
   fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
-    unimplemented!();
+    Err(io::Error::new(
+      io::ErrorKind::Other,
+      "synchronous reads are not supported on HttpBody",
+    ))
   }
 }
 
@@ -88,6 +121,22 @@ impl AsyncRead for HttpBody {
   }
 }
 
+#[test]
+fn test_body_content_length() {
+  let body = HttpBody::from(Body::from("hello world"));
+  assert_eq!(body.content_length(), Some(11));
+  let body = HttpBody::from(Body::empty());
+  assert_eq!(body.content_length(), Some(0));
+}
+
+#[test]
+fn test_body_capacity_hint() {
+  let mut body = HttpBody::from(Body::from("hello world"));
+  assert_eq!(body.capacity_hint(), 11);
+  body.hint_capacity(4096);
+  assert_eq!(body.capacity_hint(), 4096);
+}
+
 #[test]
 fn test_body_async_read() {
   use std::str::from_utf8;
@@ -8,6 +8,7 @@
 use crate::compiler::compile_sync;
 use crate::compiler::ModuleMetaData;
 use crate::deno_dir;
+use crate::errors;
 use crate::errors::DenoError;
 use crate::errors::DenoResult;
 use crate::errors::RustOrJsError;
@@ -204,6 +205,9 @@ impl Isolate {
       load_snapshot: snapshot,
       shared: libdeno::deno_buf::empty(), // TODO Use for message passing.
       recv_cb: pre_dispatch,
+      heap_limit_mb: 0,
+      heap_limit_cb: None,
+      recv_bufs_cb: None,
     };
     let libdeno_isolate = unsafe { libdeno::deno_new(config) };
     // This channel handles sending async messages back to the runtime.
@@ -248,9 +252,11 @@ impl Isolate {
       None
     } else {
       let cstr = unsafe { CStr::from_ptr(ptr) };
-      let v8_exception = cstr.to_str().unwrap();
+      let v8_exception = cstr.to_string_lossy();
       debug!("v8_exception\n{}\n", v8_exception);
-      let js_error = JSError::from_v8_exception(v8_exception).unwrap();
+      // Not every libdeno failure is reported as exception JSON.
+      let js_error = JSError::from_v8_exception(&v8_exception)
+        .unwrap_or_else(|| JSError::from_message(v8_exception.into_owned()));
       let js_error_mapped = apply_source_map(&js_error, &self.state.dir);
       Some(js_error_mapped)
     }
@@ -325,7 +331,8 @@ impl Isolate {
       let specifier_ptr =
         unsafe { libdeno::deno_mod_imports_get(self.libdeno_isolate, id, i) };
       let specifier_c: &CStr = unsafe { CStr::from_ptr(specifier_ptr) };
-      let specifier: &str = specifier_c.to_str().unwrap();
+      let specifier = specifier_c.to_string_lossy();
+      let specifier: &str = &specifier;
 
       // TODO(ry) This shouldn't be necessary here. builtin modules should be
       // taken care of at the libdeno level.
@@ -407,7 +414,9 @@ impl Isolate {
     Ok(())
   }
 
-  pub fn respond(&self, zero_copy_id: usize, buf: Buf) {
+  /// Hands `buf` to JavaScript. Fails if it couldn't be delivered, e.g. when
+  /// `libdeno.recv()` was never called or execution was terminated.
+  pub fn respond(&self, zero_copy_id: usize, buf: Buf) -> DenoResult<()> {
     self.state.metrics_op_completed(buf.len());
 
     // This will be cleaned up in the future.
@@ -419,21 +428,32 @@ impl Isolate {
 
     // deno_respond will memcpy the buf into V8's heap,
     // so borrowing a reference here is sufficient.
-    unsafe {
+    let rc = unsafe {
       libdeno::deno_respond(
         self.libdeno_isolate,
         self.as_raw_ptr(),
         buf.as_ref().into(),
       )
+    };
+    if rc != 0 {
+      let msg = match self.last_exception() {
+        Some(err) => err.message,
+        None => String::from("response was not delivered"),
+      };
+      return Err(errors::new(errors::ErrorKind::Other, msg));
     }
+    Ok(())
   }
 
   fn complete_op(&self, zero_copy_id: usize, buf: Buf) {
     // Receiving a message on rx exactly corresponds to an async task
     // completing.
     self.ntasks_decrement();
-    // Call into JS with the buf.
-    self.respond(zero_copy_id, buf);
+    // Call into JS with the buf. A failed delivery is also recorded as the
+    // last exception, which the event loop reports.
+    if let Err(err) = self.respond(zero_copy_id, buf) {
+      debug!("complete_op respond failed: {}", err);
+    }
   }
 
   fn timeout(&self) {
@@ -521,7 +541,8 @@ extern "C" fn resolve_cb(
 ) -> libdeno::deno_mod {
   let isolate = unsafe { Isolate::from_raw_ptr(user_data) };
   let specifier_c: &CStr = unsafe { CStr::from_ptr(specifier_ptr) };
-  let specifier: &str = specifier_c.to_str().unwrap();
+  let specifier = specifier_c.to_string_lossy();
+  let specifier: &str = &specifier;
   isolate
     .state
     .metrics
@@ -566,7 +587,8 @@ extern "C" fn pre_dispatch(
       isolate.state.metrics_op_completed(buf.len());
     } else {
       // Set the synchronous response, the value returned from isolate.send().
-      isolate.respond(zero_copy_id, buf);
+      // A synchronous response is always delivered.
+      isolate.respond(zero_copy_id, buf).unwrap();
     }
   } else {
     // Execute op asynchronously.
@@ -637,6 +659,16 @@ mod tests {
     });
   }
 
+  #[test]
+  fn test_respond_without_recv() {
+    let state = IsolateState::mock();
+    let snapshot = libdeno::deno_buf::empty();
+    let isolate = Isolate::new(snapshot, state, dispatch_sync);
+    let buf = vec![1u8, 2, 3].into_boxed_slice();
+    let err = isolate.respond(0, buf).unwrap_err();
+    assert_eq!(err.kind(), errors::ErrorKind::Other);
+  }
+
   fn dispatch_sync(
     _isolate: &Isolate,
     control: libdeno::deno_buf,
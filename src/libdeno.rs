@@ -8,6 +8,7 @@ use libc::c_char;
 use libc::c_int;
 use libc::c_void;
 use libc::size_t;
+use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::ptr::null;
 
@@ -59,6 +60,80 @@ impl deno_buf {
       zero_copy_id: 0,
     }
   }
+
+  /// Unlike going through `Deref`, this does not create a slice, so it is also
+  /// fine to call on `deno_buf::empty()`, whose "data_ptr" is null.
+  #[inline]
+  pub fn len(&self) -> usize {
+    self.data_len
+  }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.data_len == 0
+  }
+
+  /// Moves the contents of `v` into a `deno_buf` with "alloc_ptr" set, so that
+  /// it can be handed to C side by `deno_respond`, which takes ownership of
+  /// the allocation. This relies on Rust using the same `malloc()` as V8.
+  /// If the buffer is not passed to `deno_respond`, reclaim it with
+  /// `into_vec`, otherwise it will be leaked.
+  pub fn from_vec(mut v: Vec<u8>) -> Self {
+    if v.capacity() == 0 {
+      return Self::empty();
+    }
+    let ptr = v.as_mut_ptr();
+    let buf = Self {
+      alloc_ptr: ptr,
+      alloc_len: v.capacity(),
+      data_ptr: ptr,
+      data_len: v.len(),
+      zero_copy_id: 0,
+    };
+    mem::forget(v);
+    buf
+  }
+
+  /// Turns a buffer created by `from_vec` back into a `Vec<u8>`.
+  ///
+  /// This is unsafe because the buffer must have been created by `from_vec`
+  /// and must not have been passed to C side.
+  pub unsafe fn into_vec(self) -> Vec<u8> {
+    if self.alloc_ptr.is_null() {
+      return Vec::new();
+    }
+    let ptr = self.alloc_ptr as *mut u8;
+    Vec::from_raw_parts(ptr, self.data_len, self.alloc_len)
+  }
+
+  /// Iterates over the contents in chunks of `size` bytes; the last chunk
+  /// may be shorter. Panics if `size` is 0.
+  pub fn chunks(&self, size: usize) -> impl Iterator<Item = &[u8]> {
+    let data: &[u8] = if self.is_empty() { &[] } else { &*self };
+    data.chunks(size)
+  }
+
+  /// Splits the buffer at `mid` into two borrowed, read-only buffers without
+  /// copying. Like any borrowed `deno_buf`, they must not outlive `self`.
+  /// Splitting an empty buffer, including `deno_buf::empty()`, gives two
+  /// empty buffers. Panics if `mid > len`.
+  pub fn split_at(&self, mid: usize) -> (deno_buf, deno_buf) {
+    assert!(mid <= self.data_len, "mid out of bounds");
+    if self.is_empty() || self.data_ptr.is_null() {
+      return (Self::empty(), Self::empty());
+    }
+    let borrowed = |ptr: *const u8, len: usize| Self {
+      alloc_ptr: null(),
+      alloc_len: 0,
+      data_ptr: ptr,
+      data_len: len,
+      zero_copy_id: 0,
+    };
+    (
+      borrowed(self.data_ptr, mid),
+      borrowed(self.data_ptr.wrapping_add(mid), self.data_len - mid),
+    )
+  }
 }
 
 /// Converts Rust &Buf to libdeno `deno_buf`.
@@ -119,6 +194,14 @@ type deno_recv_cb = unsafe extern "C" fn(
   zero_copy_buf: deno_buf,
 );
 
+#[allow(non_camel_case_types)]
+type deno_recv_bufs_cb = unsafe extern "C" fn(
+  user_data: *mut c_void,
+  control_buf: deno_buf,
+  zero_copy_bufs: *const deno_buf,
+  zero_copy_bufs_len: size_t,
+);
+
 #[allow(non_camel_case_types)]
 pub type deno_mod = i32;
 
@@ -129,12 +212,31 @@ type deno_resolve_cb = unsafe extern "C" fn(
   referrer: deno_mod,
 ) -> deno_mod;
 
+#[allow(non_camel_case_types)]
+pub type deno_heap_limit_cb = unsafe extern "C" fn(
+  user_data: *mut c_void,
+  current_heap_limit: size_t,
+  initial_heap_limit: size_t,
+) -> size_t;
+
 #[repr(C)]
 pub struct deno_config {
   pub will_snapshot: c_int,
   pub load_snapshot: deno_buf,
   pub shared: deno_buf,
   pub recv_cb: deno_recv_cb,
+  pub heap_limit_mb: size_t,
+  pub heap_limit_cb: Option<deno_heap_limit_cb>,
+  pub recv_bufs_cb: Option<deno_recv_bufs_cb>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct deno_heap_stats {
+  pub total_heap_size: size_t,
+  pub used_heap_size: size_t,
+  pub heap_size_limit: size_t,
+  pub external_memory: size_t,
 }
 
 extern "C" {
@@ -143,7 +245,11 @@ extern "C" {
   pub fn deno_set_v8_flags(argc: *mut c_int, argv: *mut *mut c_char);
   pub fn deno_new(config: deno_config) -> *const isolate;
   pub fn deno_delete(i: *const isolate);
+  pub fn deno_get_snapshot(i: *const isolate) -> deno_buf;
+  pub fn deno_snapshot_delete(snapshot: deno_buf);
   pub fn deno_last_exception(i: *const isolate) -> *const c_char;
+  pub fn deno_terminate_execution(i: *const isolate);
+  pub fn deno_get_heap_stats(i: *const isolate) -> deno_heap_stats;
   pub fn deno_check_promise_errors(i: *const isolate);
   pub fn deno_lock(i: *const isolate);
   pub fn deno_unlock(i: *const isolate);
@@ -151,7 +257,7 @@ extern "C" {
     i: *const isolate,
     user_data: *const c_void,
     buf: deno_buf,
-  );
+  ) -> c_int;
   pub fn deno_zero_copy_release(i: *const isolate, zero_copy_id: usize);
   pub fn deno_execute(
     i: *const isolate,
@@ -190,3 +296,59 @@ extern "C" {
     id: deno_mod,
   );
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_deno_buf_len() {
+    let buf = deno_buf::empty();
+    assert_eq!(buf.len(), 0);
+    assert!(buf.is_empty());
+    let data = b"hello";
+    let buf = deno_buf::from(&data[..]);
+    assert_eq!(buf.len(), buf.data_len);
+    assert_eq!(buf.len(), 5);
+  }
+
+  #[test]
+  fn test_deno_buf_from_vec() {
+    let mut v = Vec::with_capacity(16);
+    v.extend_from_slice(b"hello");
+    let mut buf = deno_buf::from_vec(v);
+    assert_eq!(buf.len(), 5);
+    assert!(!buf.is_empty());
+    assert_eq!(&*buf, b"hello");
+    // Owned buffers can be modified.
+    buf[0] = b'j';
+    let v = unsafe { buf.into_vec() };
+    assert_eq!(v, b"jello");
+    assert_eq!(v.capacity(), 16);
+
+    let buf = deno_buf::from_vec(Vec::new());
+    assert!(buf.is_empty());
+    assert!(unsafe { buf.into_vec() }.is_empty());
+  }
+
+  #[test]
+  fn test_deno_buf_split_at() {
+    let data = b"0123456789";
+    let buf = deno_buf::from(&data[..]);
+    let (head, tail) = buf.split_at(4);
+    assert_eq!(&*head, b"0123");
+    assert_eq!(&*tail, b"456789");
+    let chunks: Vec<&[u8]> = buf.chunks(4).collect();
+    assert_eq!(chunks, vec![&b"0123"[..], &b"4567"[..], &b"89"[..]]);
+    assert_eq!(deno_buf::empty().chunks(4).count(), 0);
+    let (head, tail) = deno_buf::empty().split_at(0);
+    assert!(head.is_empty() && head.data_ptr.is_null());
+    assert!(tail.is_empty() && tail.data_ptr.is_null());
+  }
+
+  #[test]
+  #[should_panic(expected = "mid out of bounds")]
+  fn test_deno_buf_split_at_out_of_bounds() {
+    deno_buf::empty().split_at(1);
+  }
+}
@@ -14,11 +14,14 @@ pub mod deno_dir;
 pub mod errors;
 pub mod flags;
 mod fs;
+#[cfg(target_os = "linux")]
+mod fs_watcher;
 mod http_body;
 mod http_util;
 pub mod isolate;
 pub mod js_errors;
 pub mod libdeno;
+mod mem_pipe;
 pub mod modules;
 pub mod msg;
 pub mod msg_util;
@@ -35,6 +38,8 @@ pub mod workers;
 
 #[cfg(unix)]
 mod eager_unix;
+#[cfg(windows)]
+mod eager_windows;
 
 use log::{LevelFilter, Metadata, Record};
 use std::env;
@@ -0,0 +1,131 @@
+// Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
+
+use futures::sync::mpsc;
+use futures::Async;
+use futures::Poll;
+use futures::Sink;
+use futures::Stream;
+use std::cmp::min;
+use std::io;
+use std::io::{Read, Write};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// Creates an in-memory pipe. Bytes written to the `MemPipeWriter` can be read
+/// back from the `MemPipeReader`. At most `capacity` writes can be buffered
+/// before the writer reports `NotReady`.
+pub fn pipe(capacity: usize) -> (MemPipeReader, MemPipeWriter) {
+  let (tx, rx) = mpsc::channel(capacity);
+  let reader = MemPipeReader {
+    rx,
+    chunk: None,
+    pos: 0,
+  };
+  let writer = MemPipeWriter { tx };
+  (reader, writer)
+}
+
+/// The read half of `pipe()`. Reads return 0 (EOF) once the writer has been
+/// dropped and all buffered bytes have been consumed.
+pub struct MemPipeReader {
+  rx: mpsc::Receiver<Vec<u8>>,
+  chunk: Option<Vec<u8>>,
+  pos: usize,
+}
+
+/// The write half of `pipe()`.
+pub struct MemPipeWriter {
+  tx: mpsc::Sender<Vec<u8>>,
+}
+
+fn broken_pipe() -> io::Error {
+  io::Error::new(io::ErrorKind::BrokenPipe, "pipe reader has been closed")
+}
+
+fn poll_to_io<T>(p: Poll<T, io::Error>) -> io::Result<T> {
+  match p? {
+    Async::Ready(t) => Ok(t),
+    Async::NotReady => Err(io::ErrorKind::WouldBlock.into()),
+  }
+}
+
+impl MemPipeReader {
+  /// Resolves once a read would not return `NotReady`, because bytes are
+  /// buffered or the writer is gone. Nothing is consumed.
+  pub fn poll_read_ready(&mut self) -> Poll<(), io::Error> {
+    if self.chunk.is_none() {
+      match self.rx.poll() {
+        Err(()) => unreachable!(),
+        Ok(Async::NotReady) => return Ok(Async::NotReady),
+        Ok(Async::Ready(None)) => {}
+        Ok(Async::Ready(Some(chunk))) => {
+          self.chunk = Some(chunk);
+          self.pos = 0;
+        }
+      }
+    }
+    Ok(Async::Ready(()))
+  }
+}
+
+impl Read for MemPipeReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    poll_to_io(self.poll_read(buf))
+  }
+}
+
+impl AsyncRead for MemPipeReader {
+  fn poll_read(&mut self, buf: &mut [u8]) -> Poll<usize, io::Error> {
+    if self.chunk.is_none() {
+      match self.rx.poll() {
+        Err(()) => unreachable!(),
+        Ok(Async::NotReady) => return Ok(Async::NotReady),
+        Ok(Async::Ready(None)) => return Ok(Async::Ready(0)),
+        Ok(Async::Ready(Some(chunk))) => {
+          self.chunk = Some(chunk);
+          self.pos = 0;
+        }
+      }
+    }
+
+    let chunk = self.chunk.take().unwrap();
+    let n = min(buf.len(), chunk.len() - self.pos);
+    buf[..n].copy_from_slice(&chunk[self.pos..self.pos + n]);
+    self.pos += n;
+    if self.pos < chunk.len() {
+      self.chunk = Some(chunk);
+    }
+    Ok(Async::Ready(n))
+  }
+}
+
+impl Write for MemPipeWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    poll_to_io(self.poll_write(buf))
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    poll_to_io(self.poll_flush())
+  }
+}
+
+impl AsyncWrite for MemPipeWriter {
+  fn poll_write(&mut self, buf: &[u8]) -> Poll<usize, io::Error> {
+    if buf.is_empty() {
+      return Ok(Async::Ready(0));
+    }
+    try_ready!(self.tx.poll_ready().map_err(|_| broken_pipe()));
+    self
+      .tx
+      .start_send(buf.to_vec())
+      .map_err(|_| broken_pipe())?;
+    Ok(Async::Ready(buf.len()))
+  }
+
+  fn poll_flush(&mut self) -> Poll<(), io::Error> {
+    self.tx.poll_complete().map_err(|_| broken_pipe())
+  }
+
+  fn shutdown(&mut self) -> Poll<(), io::Error> {
+    self.tx.close().map_err(|_| broken_pipe())
+  }
+}
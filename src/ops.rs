@@ -44,7 +44,6 @@ use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio;
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
-use tokio_process::CommandExt;
 use tokio_threadpool;
 
 #[cfg(unix)]
@@ -257,11 +256,9 @@ fn op_start(
   let exec_path =
     builder.create_string(std::env::current_exe().unwrap().to_str().unwrap());
 
-  let v8_version = version::v8();
-  let v8_version_off = builder.create_string(v8_version);
-
-  let deno_version = version::DENO;
-  let deno_version_off = builder.create_string(deno_version);
+  let versions = version::get();
+  let v8_version_off = builder.create_string(versions.v8);
+  let deno_version_off = builder.create_string(versions.deno);
 
   let main_module = state.main_module().map(|m| builder.create_string(&m));
 
@@ -508,7 +505,7 @@ fn op_fetch(
         let builder = &mut FlatBufferBuilder::new();
         let header_off = msg_util::serialize_http_response(builder, &res);
         let body = res.into_body();
-        let body_resource = resources::add_hyper_body(body);
+        let body_resource = resources::add_hyper_body(body)?;
         let inner = msg::FetchRes::create(
           builder,
           &msg::FetchResArgs {
@@ -678,7 +675,7 @@ fn op_open(
   let filename = PathBuf::from(&filename_str);
   let mode = inner.mode().unwrap();
 
-  let mut open_options = tokio::fs::OpenOptions::new();
+  let mut open_options = fs::OpenOptions::new();
 
   match mode {
     "r" => {
@@ -735,11 +732,8 @@ fn op_open(
     }
   }
 
-  let op = open_options
-    .open(filename)
-    .map_err(DenoError::from)
-    .and_then(move |fs_file| -> OpResult {
-      let resource = resources::add_fs_file(fs_file);
+  let op = resources::open_file(filename, open_options, None).and_then(
+    move |resource| -> OpResult {
       let builder = &mut FlatBufferBuilder::new();
       let inner =
         msg::OpenRes::create(builder, &msg::OpenResArgs { rid: resource.rid });
@@ -752,7 +746,8 @@ fn op_open(
           ..Default::default()
         },
       ))
-    });
+    },
+  );
   Box::new(op)
 }
 
@@ -764,13 +759,8 @@ fn op_close(
   assert_eq!(data.len(), 0);
   let inner = base.inner_as_close().unwrap();
   let rid = inner.rid();
-  match resources::lookup(rid) {
-    None => odd_future(errors::bad_resource()),
-    Some(resource) => {
-      resource.close();
-      ok_future(empty_buf())
-    }
-  }
+  let op = resources::close_flushing(rid).map(|()| empty_buf());
+  Box::new(op)
 }
 
 fn op_shutdown(
@@ -1289,7 +1279,10 @@ fn op_repl_start(
   debug!("op_repl_start {}", history_file);
   let history_path = repl::history_path(&state.dir, &history_file);
   let repl = repl::Repl::new(history_path);
-  let resource = resources::add_repl(repl);
+  let resource = match resources::add_repl(repl) {
+    Ok(resource) => resource,
+    Err(err) => return odd_future(err),
+  };
 
   let builder = &mut FlatBufferBuilder::new();
   let inner = msg::ReplStartRes::create(
@@ -1386,7 +1379,7 @@ fn op_listen(
     let addr = resolve_addr(address).wait()?;
 
     let listener = TcpListener::bind(&addr)?;
-    let resource = resources::add_tcp_listener(listener);
+    let resource = resources::add_tcp_listener(listener)?;
 
     let builder = &mut FlatBufferBuilder::new();
     let inner = msg::ListenRes::create(
@@ -1406,14 +1399,18 @@ fn op_listen(
 }
 
 fn new_conn(cmd_id: u32, tcp_stream: TcpStream) -> OpResult {
-  let tcp_stream_resource = resources::add_tcp_stream(tcp_stream);
+  let tcp_stream_resource = resources::add_tcp_stream(tcp_stream)?;
+  new_conn_response(cmd_id, tcp_stream_resource.rid)
+}
+
+fn new_conn_response(cmd_id: u32, rid: resources::ResourceId) -> OpResult {
   // TODO forward socket_addr to client.
 
   let builder = &mut FlatBufferBuilder::new();
   let inner = msg::NewConn::create(
     builder,
     &msg::NewConnArgs {
-      rid: tcp_stream_resource.rid,
+      rid,
       ..Default::default()
     },
   );
@@ -1446,8 +1443,14 @@ fn op_accept(
     Some(server_resource) => {
       let op = resources::eager_accept(server_resource)
         .map_err(DenoError::from)
-        .and_then(move |(tcp_stream, _socket_addr)| {
-          new_conn(cmd_id, tcp_stream)
+        .and_then(move |accepted| match accepted {
+          resources::Accepted::Tcp(tcp_stream, _socket_addr) => {
+            new_conn(cmd_id, tcp_stream)
+          }
+          #[cfg(unix)]
+          resources::Accepted::Unix(resource, _socket_addr) => {
+            new_conn_response(cmd_id, resource.rid)
+          }
         });
       Box::new(op)
     }
@@ -1518,7 +1521,7 @@ fn op_resources(
   let res: Vec<_> = serialized_resources
     .iter()
     .map(|(key, value)| {
-      let repr = builder.create_string(value);
+      let repr = builder.create_string(value.as_str());
 
       msg::Resource::create(
         builder,
@@ -1548,14 +1551,6 @@ fn op_resources(
   ))
 }
 
-fn subprocess_stdio_map(v: msg::ProcessStdio) -> std::process::Stdio {
-  match v {
-    msg::ProcessStdio::Inherit => std::process::Stdio::inherit(),
-    msg::ProcessStdio::Piped => std::process::Stdio::piped(),
-    msg::ProcessStdio::Null => std::process::Stdio::null(),
-  }
-}
-
 fn op_run(
   state: &Arc<IsolateState>,
   base: &msg::Base<'_>,
@@ -1574,6 +1569,10 @@ fn op_run(
   let env = inner.env().unwrap();
   let cwd = inner.cwd();
 
+  let stdin = inner.stdin();
+  let stdout = inner.stdout();
+  let stderr = inner.stderr();
+
   let mut c = Command::new(args.get(0));
   (1..args.len()).for_each(|i| {
     let arg = args.get(i);
@@ -1585,34 +1584,25 @@ fn op_run(
     c.env(entry.key().unwrap(), entry.value().unwrap());
   });
 
-  c.stdin(subprocess_stdio_map(inner.stdin()));
-  c.stdout(subprocess_stdio_map(inner.stdout()));
-  c.stderr(subprocess_stdio_map(inner.stderr()));
-
-  // Spawn the command.
-  let child = match c.spawn_async() {
-    Ok(v) => v,
-    Err(err) => {
-      return odd_future(err.into());
-    }
+  // Spawn the command. This resolves right away.
+  let resources = match resources::spawn(c, stdin, stdout, stderr).wait() {
+    Ok(resources) => resources,
+    Err(err) => return odd_future(err),
   };
 
-  let pid = child.id();
-  let resources = resources::add_child(child);
-
   let mut res_args = msg::RunResArgs {
     rid: resources.child_rid,
-    pid,
+    pid: resources.pid,
     ..Default::default()
   };
 
-  if let Some(stdin_rid) = resources.stdin_rid {
+  if let Some(stdin_rid) = resources.stdin.rid() {
     res_args.stdin_rid = stdin_rid;
   }
-  if let Some(stdout_rid) = resources.stdout_rid {
+  if let Some(stdout_rid) = resources.stdout.rid() {
     res_args.stdout_rid = stdout_rid;
   }
-  if let Some(stderr_rid) = resources.stderr_rid {
+  if let Some(stderr_rid) = resources.stderr.rid() {
     res_args.stderr_rid = stderr_rid;
   }
 
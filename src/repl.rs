@@ -1,5 +1,7 @@
 // Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
 use rustyline;
+use rustyline::error::ReadlineError;
+use rustyline::Config;
 
 use crate::msg::ErrorKind;
 use std::error::Error;
@@ -29,9 +31,9 @@ unsafe impl<T: rustyline::Helper> Send for Editor<T> {}
 
 #[cfg(windows)]
 impl<T: rustyline::Helper> Editor<T> {
-  pub fn new() -> Editor<T> {
+  pub fn with_config(config: Config) -> Editor<T> {
     Editor {
-      inner: rustyline::Editor::<T>::new(),
+      inner: rustyline::Editor::<T>::with_config(config),
     }
   }
 }
@@ -52,6 +54,9 @@ impl<T: rustyline::Helper> DerefMut for Editor<T> {
   }
 }
 
+/// How many lines of history are kept by `Repl::new()`.
+pub const DEFAULT_MAX_HISTORY: usize = 100;
+
 pub struct Repl {
   editor: Editor<()>,
   history_file: PathBuf,
@@ -59,8 +64,18 @@ pub struct Repl {
 
 impl Repl {
   pub fn new(history_file: PathBuf) -> Self {
+    Self::with_max_history(history_file, DEFAULT_MAX_HISTORY)
+  }
+
+  /// Creates a repl that keeps at most `max_history` lines of history. Lines
+  /// equal to the one entered right before them are not added to the history.
+  pub fn with_max_history(history_file: PathBuf, max_history: usize) -> Self {
+    let config = Config::builder()
+      .max_history_size(max_history)
+      .history_ignore_dups(true)
+      .build();
     let mut repl = Self {
-      editor: Editor::<()>::new(),
+      editor: Editor::<()>::with_config(config),
       history_file,
     };
 
@@ -78,7 +93,9 @@ impl Repl {
       .unwrap_or(())
   }
 
-  fn save_history(&mut self) -> DenoResult<()> {
+  /// Writes the history back to the history file. The loaded history is
+  /// written along with the new lines, so nothing is lost between sessions.
+  pub fn save_history(&mut self) -> DenoResult<()> {
     self
       .editor
       .save_history(&self.history_file.to_str().unwrap())
@@ -89,16 +106,58 @@ impl Repl {
       })
   }
 
+  pub fn add_history_entry(&mut self, line: &str) -> bool {
+    self.editor.add_history_entry(line)
+  }
+
+  /// Returns the history, oldest line first.
+  pub fn history(&self) -> Vec<String> {
+    self.editor.history().iter().cloned().collect()
+  }
+
   pub fn readline(&mut self, prompt: &str) -> DenoResult<String> {
+    // Forward EOF to TS side as an error for processing
     self
-      .editor
-      .readline(&prompt)
-      .map(|line| {
-        self.editor.add_history_entry(line.as_ref());
-        line
-      }).map_err(|e| deno_error(ErrorKind::Other, e.description().to_string()))
-    // Forward error to TS side for processing
+      .readline_or_eof(prompt)?
+      .ok_or_else(|| deno_error(ErrorKind::Other, String::from("EOF")))
   }
+
+  /// Like `readline()`, but returns None on EOF (Ctrl-D) instead of an error.
+  /// Ctrl-C yields an error of kind `Interrupted`.
+  pub fn readline_or_eof(
+    &mut self,
+    prompt: &str,
+  ) -> DenoResult<Option<String>> {
+    let maybe_line = readline_result(self.editor.readline(&prompt))?;
+    if let Some(ref line) = maybe_line {
+      self.editor.add_history_entry(line.as_ref());
+    }
+    Ok(maybe_line)
+  }
+}
+
+fn readline_result(
+  r: Result<String, ReadlineError>,
+) -> DenoResult<Option<String>> {
+  match r {
+    Ok(line) => Ok(Some(line)),
+    Err(ReadlineError::Eof) => Ok(None),
+    Err(ReadlineError::Interrupted) => Err(deno_error(
+      ErrorKind::Interrupted,
+      String::from("Interrupted"),
+    )),
+    Err(e) => Err(deno_error(ErrorKind::Other, e.description().to_string())),
+  }
+}
+
+#[test]
+fn test_readline_result() {
+  let line = readline_result(Ok(String::new())).unwrap();
+  assert_eq!(line, Some(String::new()));
+  assert_eq!(readline_result(Err(ReadlineError::Eof)).unwrap(), None);
+  let err = readline_result(Err(ReadlineError::Interrupted)).unwrap_err();
+  assert_eq!(err.kind(), ErrorKind::Interrupted);
+  assert_eq!(err.to_string(), "Interrupted");
 }
 
 impl Drop for Repl {
@@ -10,43 +10,335 @@
 
 #[cfg(unix)]
 use crate::eager_unix as eager;
+#[cfg(windows)]
+use crate::eager_windows as eager;
 use crate::errors;
 use crate::errors::bad_resource;
 use crate::errors::DenoError;
 use crate::errors::DenoResult;
+#[cfg(target_os = "linux")]
+use crate::fs_watcher::{FsEvent, FsWatcher};
 use crate::http_body::HttpBody;
 use crate::isolate::Buf;
 use crate::isolate::WorkerChannels;
+use crate::mem_pipe;
+use crate::msg::ProcessStdio;
 use crate::repl::Repl;
 use crate::tokio_util;
 use crate::tokio_write;
 
+use atty;
+
 use futures;
-use futures::future::{Either, FutureResult};
+use futures::future::{Either, FutureResult, Loop};
+use futures::sync::oneshot;
+use futures::Async;
 use futures::Future;
 use futures::Poll;
 use futures::Sink;
 use futures::Stream;
 use hyper;
+use mio;
+#[cfg(unix)]
+use net2::unix::UnixTcpBuilderExt;
+use net2::TcpBuilder;
+use rustls::Session;
+use serde_json;
 use std;
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
 use std::io::{Error, Read, Seek, SeekFrom, Write};
-use std::net::{Shutdown, SocketAddr};
+use std::net::{Shutdown, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
 use std::process::ExitStatus;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 use tokio;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 use tokio_io;
 use tokio_process;
+use tokio_rustls;
+#[cfg(unix)]
+use tokio_signal;
+use tokio_threadpool;
 
 pub type ResourceId = u32; // Sometimes referred to RID.
 
 // These store Deno's file descriptors. These are not necessarily the operating
 // system ones.
-type ResourceTable = HashMap<ResourceId, Repr>;
+//
+// Several rids can refer to the same entry after dup(). The entry (and thus
+// the underlying OS resource) is only dropped once all of them are closed.
+struct ResourceTable {
+  // Maps every open rid to the key of its entry in `entries`.
+  rids: HashMap<ResourceId, ResourceId>,
+  entries: HashMap<ResourceId, Entry>,
+  // Number of open rids, not counting stdio. Kept up to date by insert(),
+  // dup() and remove() so that checking `max_resources` doesn't have to
+  // scan the table.
+  open_rids: usize,
+  // Upper bound on the number of open rids, not counting stdio.
+  max_resources: usize,
+  // Futures returned by `Resource::on_close()`, resolved once the rid is
+  // closed.
+  close_waiters: HashMap<ResourceId, Vec<oneshot::Sender<()>>>,
+  // The kind of every open rid. Kept behind its own lock so that lookups
+  // don't have to wait for the table, which is held during IO.
+  kinds: KindIndex,
+}
+
+type KindIndex = Arc<RwLock<HashMap<ResourceId, ResourceKind>>>;
+
+struct Entry {
+  repr: Repr,
+  refcount: usize,
+  // Shared by all rids referring to this entry.
+  bytes_read: AtomicUsize,
+  bytes_written: AtomicUsize,
+}
+
+impl Entry {
+  fn new(repr: Repr) -> Self {
+    Self {
+      repr,
+      refcount: 1,
+      bytes_read: AtomicUsize::new(0),
+      bytes_written: AtomicUsize::new(0),
+    }
+  }
+
+  fn stats(&self) -> ResourceStats {
+    ResourceStats {
+      bytes_read: self.bytes_read.load(Ordering::SeqCst) as u64,
+      bytes_written: self.bytes_written.load(Ordering::SeqCst) as u64,
+    }
+  }
+}
+
+/// IO counters of a resource, as returned by `Resource::stats()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceStats {
+  pub bytes_read: u64,
+  pub bytes_written: u64,
+}
+
+// The other ends of the stdio pipes of `ResourceTable::new_isolated()`.
+#[cfg(test)]
+struct IsolatedStdio {
+  stdin: mem_pipe::MemPipeWriter,
+  stdout: mem_pipe::MemPipeReader,
+  stderr: mem_pipe::MemPipeReader,
+}
+
+impl ResourceTable {
+  fn new() -> Self {
+    Self::with_kind_index(Arc::new(RwLock::new(HashMap::new())))
+  }
+
+  // A table for tests whose stdio rids are in-memory pipes instead of the
+  // process' stdio, so that what a test writes to "stdout" can be inspected
+  // and doesn't end up on the terminal.
+  #[cfg(test)]
+  fn new_isolated() -> (Self, IsolatedStdio) {
+    let mut table = Self::new();
+    let (stdin_reader, stdin) = mem_pipe::pipe(PIPE_CAPACITY);
+    let (stdout, stdout_writer) = mem_pipe::pipe(PIPE_CAPACITY);
+    let (stderr, stderr_writer) = mem_pipe::pipe(PIPE_CAPACITY);
+    table.insert(0, Repr::MemPipeReader(stdin_reader));
+    table.insert(1, Repr::MemPipeWriter(stdout_writer));
+    table.insert(2, Repr::MemPipeWriter(stderr_writer));
+    let stdio = IsolatedStdio {
+      stdin,
+      stdout,
+      stderr,
+    };
+    (table, stdio)
+  }
+
+  fn with_kind_index(kinds: KindIndex) -> Self {
+    Self {
+      rids: HashMap::new(),
+      entries: HashMap::new(),
+      open_rids: 0,
+      max_resources: usize::max_value(),
+      close_waiters: HashMap::new(),
+      kinds,
+    }
+  }
+
+  // Updates the kind of all rids referring to the entry `key`.
+  fn refresh_kinds(&self, key: ResourceId) {
+    let kind = ResourceKind::from(&self.entries[&key].repr);
+    let mut kinds = self.kinds.write().unwrap();
+    for (rid, k) in self.rids.iter() {
+      if *k == key {
+        kinds.insert(*rid, kind);
+      }
+    }
+  }
+
+  // Number of open rids, not counting stdio.
+  fn len(&self) -> usize {
+    self.open_rids
+  }
+
+  // Records that `rid` now refers to the entry `key`.
+  fn insert_rid(&mut self, rid: ResourceId, key: ResourceId) {
+    if self.rids.insert(rid, key).is_none() && rid > 2 {
+      self.open_rids += 1;
+    }
+  }
+
+  fn check_max_resources(&self) -> DenoResult<()> {
+    if self.len() >= self.max_resources {
+      return Err(errors::new(
+        errors::ErrorKind::TooManyOpenFiles,
+        String::from("Too many open resources"),
+      ));
+    }
+    Ok(())
+  }
+
+  fn contains_key(&self, rid: &ResourceId) -> bool {
+    self.rids.contains_key(rid)
+  }
+
+  fn get(&self, rid: &ResourceId) -> Option<&Repr> {
+    self.entry(rid).map(|entry| &entry.repr)
+  }
+
+  fn entry(&self, rid: &ResourceId) -> Option<&Entry> {
+    let key = self.rids.get(rid)?;
+    self.entries.get(key)
+  }
+
+  fn get_mut(&mut self, rid: &ResourceId) -> Option<&mut Repr> {
+    let key = self.rids.get(rid)?;
+    self.entries.get_mut(key).map(|entry| &mut entry.repr)
+  }
+
+  fn insert(&mut self, rid: ResourceId, repr: Repr) {
+    self
+      .kinds
+      .write()
+      .unwrap()
+      .insert(rid, ResourceKind::from(&repr));
+    self.insert_rid(rid, rid);
+    self.entries.insert(rid, Entry::new(repr));
+  }
+
+  // Makes `new_rid` refer to the same entry as `rid`.
+  fn dup(&mut self, rid: ResourceId, new_rid: ResourceId) -> Option<()> {
+    let key = *self.rids.get(&rid)?;
+    self.entries.get_mut(&key)?.refcount += 1;
+    self.insert_rid(new_rid, key);
+    self.refresh_kinds(key);
+    Some(())
+  }
+
+  // Closes `rid`. Returns None if it was not open, otherwise whether this was
+  // the last rid referring to its entry, along with the dropped repr if so.
+  fn remove(&mut self, rid: &ResourceId) -> Option<Option<Repr>> {
+    let key = self.rids.remove(rid)?;
+    if *rid > 2 {
+      self.open_rids -= 1;
+    }
+    self.kinds.write().unwrap().remove(rid);
+    for waiter in self.close_waiters.remove(rid).unwrap_or_default() {
+      // The future may have been dropped already.
+      let _ = waiter.send(());
+    }
+    let entry = self.entries.get_mut(&key).unwrap();
+    entry.refcount -= 1;
+    if entry.refcount > 0 {
+      return Some(None);
+    }
+    Some(self.entries.remove(&key).map(|entry| entry.repr))
+  }
+
+  // Moves the repr for `rid` out of the table, passes it to `f` and stores
+  // the repr that `f` hands back in the same entry, so that dup'd rids keep
+  // pointing at it.
+  fn map_repr<F, R>(&mut self, rid: &ResourceId, f: F) -> Option<R>
+  where
+    F: FnOnce(Repr) -> (Repr, R),
+  {
+    let key = *self.rids.get(rid)?;
+    let entry = self.entries.remove(&key)?;
+    let kind = ResourceKind::from(&entry.repr);
+    let (repr, r) = f(entry.repr);
+    let kind_changed = ResourceKind::from(&repr) != kind;
+    self.entries.insert(
+      key,
+      Entry {
+        repr,
+        refcount: entry.refcount,
+        bytes_read: entry.bytes_read,
+        bytes_written: entry.bytes_written,
+      },
+    );
+    if kind_changed {
+      self.refresh_kinds(key);
+    }
+    Some(r)
+  }
+
+  // Exchanges the reprs of `a` and `b`. Other rids referring to the same
+  // entries see the exchanged reprs too.
+  fn swap_repr(&mut self, a: &ResourceId, b: &ResourceId) -> Option<()> {
+    let key_a = *self.rids.get(a)?;
+    let key_b = *self.rids.get(b)?;
+    if key_a == key_b {
+      return Some(());
+    }
+    let mut entry_a = self.entries.remove(&key_a)?;
+    let mut entry_b = self.entries.remove(&key_b).unwrap();
+    std::mem::swap(&mut entry_a.repr, &mut entry_b.repr);
+    self.entries.insert(key_a, entry_a);
+    self.entries.insert(key_b, entry_b);
+    self.refresh_kinds(key_a);
+    self.refresh_kinds(key_b);
+    Some(())
+  }
+
+  fn close_all_except_stdio(&mut self) {
+    let rids: Vec<ResourceId> =
+      self.rids.keys().cloned().filter(|rid| *rid > 2).collect();
+    for rid in rids {
+      if let Some(Some(mut repr)) = self.remove(&rid) {
+        shutdown_socket(&mut repr);
+        notify_closed(&repr);
+      }
+    }
+  }
+
+  fn iter(&self) -> impl Iterator<Item = (&ResourceId, &Repr)> {
+    self.iter_entries().map(|(rid, entry)| (rid, &entry.repr))
+  }
+
+  fn iter_entries(&self) -> impl Iterator<Item = (&ResourceId, &Entry)> {
+    let entries = &self.entries;
+    self.rids.iter().map(move |(rid, key)| (rid, &entries[key]))
+  }
+
+  // Sums the counters of all entries. Dup'd rids share an entry, so they are
+  // only counted once.
+  fn io_totals(&self) -> ResourceStats {
+    let mut totals = ResourceStats::default();
+    for entry in self.entries.values() {
+      let stats = entry.stats();
+      totals.bytes_read += stats.bytes_read;
+      totals.bytes_written += stats.bytes_written;
+    }
+    totals
+  }
+}
 
 #[cfg(not(windows))]
 use std::os::unix::io::FromRawFd;
@@ -60,8 +352,9 @@ extern crate winapi;
 lazy_static! {
   // Starts at 3 because stdio is [0-2].
   static ref NEXT_RID: AtomicUsize = AtomicUsize::new(3);
+  static ref RESOURCE_KINDS: KindIndex = Arc::new(RwLock::new(HashMap::new()));
   static ref RESOURCE_TABLE: Mutex<ResourceTable> = Mutex::new({
-    let mut m = HashMap::new();
+    let mut m = ResourceTable::with_kind_index(RESOURCE_KINDS.clone());
     // TODO Load these lazily during lookup?
     m.insert(0, Repr::Stdin(tokio::io::stdin()));
 
@@ -87,6 +380,9 @@ enum Repr {
   Stdout(tokio::fs::File),
   Stderr(tokio::io::Stderr),
   FsFile(tokio::fs::File),
+  // The pending DirEntry is kept around while its metadata is being fetched,
+  // so that it is not lost if that is not ready yet.
+  FsDir(tokio::fs::ReadDir, Option<tokio::fs::DirEntry>),
   // Since TcpListener might be closed while there is a pending accept task,
   // we need to track the task so that when the listener is closed,
   // this pending task could be notified and die.
@@ -94,345 +390,4032 @@ enum Repr {
   // See: https://github.com/tokio-rs/tokio/issues/846
   TcpListener(tokio::net::TcpListener, Option<futures::task::Task>),
   TcpStream(tokio::net::TcpStream),
+  UdpSocket(tokio::net::UdpSocket),
+  // TLS sessions are large, so they are boxed like `Child` below.
+  TlsStream(Box<tokio_rustls::client::TlsStream<TcpStream>>),
+  ServerTlsStream(Box<tokio_rustls::server::TlsStream<TcpStream>>),
+  #[cfg(unix)]
+  UnixListener(tokio::net::UnixListener),
+  #[cfg(unix)]
+  UnixStream(tokio::net::UnixStream),
   HttpBody(HttpBody),
+  MemPipeReader(mem_pipe::MemPipeReader),
+  MemPipeWriter(mem_pipe::MemPipeWriter),
   Repl(Arc<Mutex<Repl>>),
   // Enum size is bounded by the largest variant.
   // Use `Box` around large `Child` struct.
   // https://rust-lang.github.io/rust-clippy/master/index.html#large_enum_variant
-  Child(Box<tokio_process::Child>),
+  Child(Box<tokio_process::Child>, ChildWaiter),
   ChildStdin(tokio_process::ChildStdin),
   ChildStdout(tokio_process::ChildStdout),
   ChildStderr(tokio_process::ChildStderr),
   Worker(WorkerChannels),
+  #[cfg(unix)]
+  Signal(tokio_signal::unix::Signal),
+  // Like TcpListener, timers track the task waiting on them, so that closing
+  // the timer wakes it up instead of leaving it pending forever.
+  Timer(tokio::timer::Delay, Option<futures::task::Task>),
+  Interval(tokio::timer::Interval, Option<futures::task::Task>),
+  #[cfg(target_os = "linux")]
+  FsWatcher(Box<FsWatcher>),
+  SharedBuf(SharedBuf),
+  EnvIter(std::vec::IntoIter<(String, String)>),
+  // The rid of the resource being read. The lines are polled without holding
+  // the table lock, since reading from the inner resource takes it again.
+  LineReader(ResourceId, Arc<Mutex<Lines>>),
+  // The host and port to look up. The lookup runs in poll_dns() through
+  // tokio_threadpool::blocking(), without holding the table lock.
+  DnsQuery(String, u16, Option<futures::task::Task>),
+}
+
+type Lines = tokio::io::Lines<std::io::BufReader<PolledResource>>;
+// The task last seen waiting on a child in `ChildStatus::poll()`.
+type ChildWaiter = Arc<Mutex<Option<futures::task::Task>>>;
+
+/// A fixed size memory region that can be registered under several rids,
+/// possibly in different isolates.
+pub type SharedBuf = Arc<Mutex<Vec<u8>>>;
+
+// The kinds of the open rids of the global table. Unlike RESOURCE_TABLE, this
+// can be read from several threads at once.
+fn kind_index() -> RwLockReadGuard<'static, HashMap<ResourceId, ResourceKind>> {
+  // Make sure stdio has been added.
+  lazy_static::initialize(&RESOURCE_TABLE);
+  RESOURCE_KINDS.read().unwrap()
 }
 
 /// If the given rid is open, this returns the type of resource, E.G. "worker".
 /// If the rid is closed or was never open, it returns None.
 pub fn get_type(rid: ResourceId) -> Option<String> {
-  let table = RESOURCE_TABLE.lock().unwrap();
-  table.get(&rid).map(inspect_repr)
+  resource_kind(rid).map(|kind| String::from(kind.as_str()))
 }
 
-pub fn table_entries() -> Vec<(u32, String)> {
-  let table = RESOURCE_TABLE.lock().unwrap();
-
-  table
-    .iter()
-    .map(|(key, value)| (*key, inspect_repr(&value)))
-    .collect()
+/// Like `get_type()`, but returns the kind itself. Ops can use this to check
+/// a rid before dispatching on it.
+pub fn resource_kind(rid: ResourceId) -> Option<ResourceKind> {
+  kind_index().get(&rid).cloned()
 }
 
 #[test]
-fn test_table_entries() {
-  let mut entries = table_entries();
-  entries.sort();
-  assert_eq!(entries[0], (0, String::from("stdin")));
-  assert_eq!(entries[1], (1, String::from("stdout")));
-  assert_eq!(entries[2], (2, String::from("stderr")));
+fn test_resource_kind() {
+  assert_eq!(resource_kind(0), Some(ResourceKind::Stdin));
+  assert_eq!(resource_kind(9999), None);
 }
 
-fn inspect_repr(repr: &Repr) -> String {
-  let h_repr = match repr {
-    Repr::Stdin(_) => "stdin",
-    Repr::Stdout(_) => "stdout",
-    Repr::Stderr(_) => "stderr",
-    Repr::FsFile(_) => "fsFile",
-    Repr::TcpListener(_, _) => "tcpListener",
-    Repr::TcpStream(_) => "tcpStream",
-    Repr::HttpBody(_) => "httpBody",
-    Repr::Repl(_) => "repl",
-    Repr::Child(_) => "child",
-    Repr::ChildStdin(_) => "childStdin",
-    Repr::ChildStdout(_) => "childStdout",
-    Repr::ChildStderr(_) => "childStderr",
-    Repr::Worker(_) => "worker",
-  };
+#[test]
+fn test_on_close() {
+  tokio_util::init(|| {
+    let addr = "127.0.0.1:0".parse().unwrap();
+    let listener =
+      add_tcp_listener(tokio::net::TcpListener::bind(&addr).unwrap()).unwrap();
+    let closed = listener.on_close();
+    let l = listener.clone();
+    tokio::spawn(futures::future::lazy(move || {
+      std::thread::sleep(Duration::from_millis(50));
+      l.close();
+      Ok(())
+    }));
+    tokio_util::block_on(closed).unwrap();
+    assert!(lookup(listener.rid).is_none());
+    // Waiting on a rid that is already closed resolves right away.
+    listener.on_close().wait().unwrap();
+  });
+}
 
-  String::from(h_repr)
+#[test]
+fn test_on_close_dropped_waiters() {
+  let (reader, writer) = new_pipe().unwrap();
+  for _ in 0..10 {
+    drop(reader.on_close());
+  }
+  let closed = reader.on_close();
+  let waiters = RESOURCE_TABLE.lock().unwrap().close_waiters[&reader.rid].len();
+  assert_eq!(waiters, 1);
+  reader.close();
+  closed.wait().unwrap();
+  writer.close();
 }
 
-// Abstract async file interface.
-// Ideally in unix, if Resource represents an OS rid, it will be the same.
-#[derive(Clone, Debug)]
-pub struct Resource {
-  pub rid: ResourceId,
+#[test]
+fn test_read_timeout() {
+  let (local, _peer) = tcp_loopback_pair();
+  let resource = add_tcp_stream(local).unwrap();
+  tokio_util::init(|| {
+    let start = Instant::now();
+    let read = resource.read_timeout(vec![0; 8], Duration::from_millis(100));
+    let err = tokio_util::block_on(read).unwrap_err();
+    assert_eq!(err.kind(), errors::ErrorKind::TimedOut);
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(100));
+    assert!(elapsed < Duration::from_secs(5));
+  });
+  // Timing out does not close the resource.
+  assert!(lookup(resource.rid).is_some());
+  resource.close();
 }
 
-impl Resource {
-  // TODO Should it return a Resource instead of net::TcpStream?
-  pub fn poll_accept(&mut self) -> Poll<(TcpStream, SocketAddr), Error> {
-    let mut table = RESOURCE_TABLE.lock().unwrap();
-    let maybe_repr = table.get_mut(&self.rid);
-    match maybe_repr {
-      None => Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "Listener has been closed",
-      )),
-      Some(repr) => match repr {
-        Repr::TcpListener(ref mut s, _) => s.poll_accept(),
-        _ => panic!("Cannot accept"),
-      },
-    }
-  }
+#[test]
+fn test_capabilities() {
+  let stdin = Resource { rid: 0 }.capabilities().unwrap();
+  assert!(stdin.readable);
+  assert!(!stdin.writable);
+  let stdout = Resource { rid: 1 }.capabilities().unwrap();
+  assert!(!stdout.readable);
+  assert!(stdout.writable);
+  assert!(!stdout.seekable);
+  assert!(!stdout.listener);
+  let caps = ResourceKind::FsFile.capabilities();
+  assert!(caps.readable && caps.writable && caps.seekable);
+  assert!(ResourceKind::TcpListener.capabilities().listener);
+  let err = Resource { rid: 9999 }.capabilities().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
 
-  /// Track the current task (for TcpListener resource).
-  /// Throws an error if another task is already tracked.
-  pub fn track_task(&mut self) -> Result<(), std::io::Error> {
-    let mut table = RESOURCE_TABLE.lock().unwrap();
-    // Only track if is TcpListener.
-    if let Some(Repr::TcpListener(_, t)) = table.get_mut(&self.rid) {
-      // Currently, we only allow tracking a single accept task for a listener.
-      // This might be changed in the future with multiple workers.
-      // Caveat: TcpListener by itself also only tracks an accept task at a time.
-      // See https://github.com/tokio-rs/tokio/issues/846#issuecomment-454208883
-      if t.is_some() {
-        return Err(std::io::Error::new(
-          std::io::ErrorKind::Other,
-          "Another accept task is ongoing",
-        ));
-      }
-      t.replace(futures::task::current());
-    }
-    Ok(())
-  }
+/// The type of an open resource. `as_str()` yields the names used on the JS
+/// side, E.G. "fsFile".
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+  Stdin,
+  Stdout,
+  Stderr,
+  FsFile,
+  FsDir,
+  TcpListener,
+  TcpStream,
+  UdpSocket,
+  TlsStream,
+  #[cfg(unix)]
+  UnixListener,
+  #[cfg(unix)]
+  UnixStream,
+  HttpBody,
+  MemPipeReader,
+  MemPipeWriter,
+  Repl,
+  Child,
+  ChildStdin,
+  ChildStdout,
+  ChildStderr,
+  Worker,
+  #[cfg(unix)]
+  Signal,
+  Timer,
+  Interval,
+  #[cfg(target_os = "linux")]
+  FsWatcher,
+  SharedBuf,
+  EnvIter,
+  LineReader,
+  DnsQuery,
+}
 
-  /// Stop tracking a task (for TcpListener resource).
-  /// Happens when the task is done and thus no further tracking is needed.
-  pub fn untrack_task(&mut self) {
-    let mut table = RESOURCE_TABLE.lock().unwrap();
-    // Only untrack if is TcpListener.
-    if let Some(Repr::TcpListener(_, t)) = table.get_mut(&self.rid) {
-      // DO NOT assert is_some here.
-      // See reasoning in Accept::poll().
-      t.take();
+impl ResourceKind {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      ResourceKind::Stdin => "stdin",
+      ResourceKind::Stdout => "stdout",
+      ResourceKind::Stderr => "stderr",
+      ResourceKind::FsFile => "fsFile",
+      ResourceKind::FsDir => "fsDir",
+      ResourceKind::TcpListener => "tcpListener",
+      ResourceKind::TcpStream => "tcpStream",
+      ResourceKind::UdpSocket => "udpSocket",
+      ResourceKind::TlsStream => "tlsStream",
+      #[cfg(unix)]
+      ResourceKind::UnixListener => "unixListener",
+      #[cfg(unix)]
+      ResourceKind::UnixStream => "unixStream",
+      ResourceKind::HttpBody => "httpBody",
+      ResourceKind::MemPipeReader => "memPipeReader",
+      ResourceKind::MemPipeWriter => "memPipeWriter",
+      ResourceKind::Repl => "repl",
+      ResourceKind::Child => "child",
+      ResourceKind::ChildStdin => "childStdin",
+      ResourceKind::ChildStdout => "childStdout",
+      ResourceKind::ChildStderr => "childStderr",
+      ResourceKind::Worker => "worker",
+      #[cfg(unix)]
+      ResourceKind::Signal => "signal",
+      ResourceKind::Timer => "timer",
+      ResourceKind::Interval => "interval",
+      #[cfg(target_os = "linux")]
+      ResourceKind::FsWatcher => "fsWatcher",
+      ResourceKind::SharedBuf => "sharedBuf",
+      ResourceKind::EnvIter => "envIter",
+      ResourceKind::LineReader => "lineReader",
+      ResourceKind::DnsQuery => "dnsQuery",
     }
   }
 
-  // close(2) is done by dropping the value. Therefore we just need to remove
-  // the resource from the RESOURCE_TABLE.
-  pub fn close(&self) {
-    let mut table = RESOURCE_TABLE.lock().unwrap();
-    let r = table.remove(&self.rid);
-    assert!(r.is_some());
-    // If TcpListener, we must kill all pending accepts!
-    if let Repr::TcpListener(_, Some(t)) = r.unwrap() {
-      // Call notify on the tracked task, so that they would error out.
-      t.notify();
+  /// The ops that resources of this kind support. This matches what
+  /// `Resource` dispatches in its `AsyncRead`, `AsyncWrite`, seek and accept
+  /// implementations.
+  pub fn capabilities(self) -> ResourceCaps {
+    let readable = match self {
+      ResourceKind::Stdin
+      | ResourceKind::FsFile
+      | ResourceKind::TcpStream
+      | ResourceKind::TlsStream
+      | ResourceKind::HttpBody
+      | ResourceKind::MemPipeReader
+      | ResourceKind::ChildStdout
+      | ResourceKind::ChildStderr => true,
+      #[cfg(unix)]
+      ResourceKind::UnixStream => true,
+      _ => false,
+    };
+    let writable = match self {
+      ResourceKind::Stdout
+      | ResourceKind::Stderr
+      | ResourceKind::FsFile
+      | ResourceKind::TcpStream
+      | ResourceKind::TlsStream
+      | ResourceKind::MemPipeWriter
+      | ResourceKind::ChildStdin => true,
+      #[cfg(unix)]
+      ResourceKind::UnixStream => true,
+      _ => false,
+    };
+    let listener = match self {
+      ResourceKind::TcpListener => true,
+      #[cfg(unix)]
+      ResourceKind::UnixListener => true,
+      _ => false,
+    };
+    ResourceCaps {
+      readable,
+      writable,
+      seekable: self == ResourceKind::FsFile,
+      listener,
     }
   }
+}
 
-  pub fn shutdown(&mut self, how: Shutdown) -> Result<(), DenoError> {
-    let mut table = RESOURCE_TABLE.lock().unwrap();
-    let maybe_repr = table.get_mut(&self.rid);
-    match maybe_repr {
-      None => panic!("bad rid"),
-      Some(repr) => match repr {
-        Repr::TcpStream(ref mut f) => {
-          TcpStream::shutdown(f, how).map_err(DenoError::from)
-        }
-        _ => panic!("Cannot shutdown"),
-      },
-    }
-  }
+/// What can be done with a resource, as returned by
+/// `Resource::capabilities()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceCaps {
+  pub readable: bool,
+  pub writable: bool,
+  pub seekable: bool,
+  pub listener: bool,
 }
 
-impl Read for Resource {
-  fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
-    unimplemented!();
+impl fmt::Display for ResourceKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.pad(self.as_str())
   }
 }
 
-impl AsyncRead for Resource {
-  fn poll_read(&mut self, buf: &mut [u8]) -> Poll<usize, Error> {
-    let mut table = RESOURCE_TABLE.lock().unwrap();
-    let maybe_repr = table.get_mut(&self.rid);
-    match maybe_repr {
-      None => panic!("bad rid"),
-      Some(repr) => match repr {
-        Repr::FsFile(ref mut f) => f.poll_read(buf),
-        Repr::Stdin(ref mut f) => f.poll_read(buf),
-        Repr::TcpStream(ref mut f) => f.poll_read(buf),
-        Repr::HttpBody(ref mut f) => f.poll_read(buf),
-        Repr::ChildStdout(ref mut f) => f.poll_read(buf),
-        Repr::ChildStderr(ref mut f) => f.poll_read(buf),
-        _ => panic!("Cannot read"),
-      },
+impl<'a> From<&'a Repr> for ResourceKind {
+  fn from(repr: &'a Repr) -> Self {
+    match repr {
+      Repr::Stdin(_) => ResourceKind::Stdin,
+      Repr::Stdout(_) => ResourceKind::Stdout,
+      Repr::Stderr(_) => ResourceKind::Stderr,
+      Repr::FsFile(_) => ResourceKind::FsFile,
+      Repr::FsDir(_, _) => ResourceKind::FsDir,
+      Repr::TcpListener(_, _) => ResourceKind::TcpListener,
+      Repr::TcpStream(_) => ResourceKind::TcpStream,
+      Repr::UdpSocket(_) => ResourceKind::UdpSocket,
+      Repr::TlsStream(_) => ResourceKind::TlsStream,
+      Repr::ServerTlsStream(_) => ResourceKind::TlsStream,
+      #[cfg(unix)]
+      Repr::UnixListener(_) => ResourceKind::UnixListener,
+      #[cfg(unix)]
+      Repr::UnixStream(_) => ResourceKind::UnixStream,
+      Repr::HttpBody(_) => ResourceKind::HttpBody,
+      Repr::MemPipeReader(_) => ResourceKind::MemPipeReader,
+      Repr::MemPipeWriter(_) => ResourceKind::MemPipeWriter,
+      Repr::Repl(_) => ResourceKind::Repl,
+      Repr::Child(_, _) => ResourceKind::Child,
+      Repr::ChildStdin(_) => ResourceKind::ChildStdin,
+      Repr::ChildStdout(_) => ResourceKind::ChildStdout,
+      Repr::ChildStderr(_) => ResourceKind::ChildStderr,
+      Repr::Worker(_) => ResourceKind::Worker,
+      #[cfg(unix)]
+      Repr::Signal(_) => ResourceKind::Signal,
+      Repr::Timer(_, _) => ResourceKind::Timer,
+      Repr::Interval(_, _) => ResourceKind::Interval,
+      #[cfg(target_os = "linux")]
+      Repr::FsWatcher(_) => ResourceKind::FsWatcher,
+      Repr::SharedBuf(_) => ResourceKind::SharedBuf,
+      Repr::EnvIter(_) => ResourceKind::EnvIter,
+      Repr::LineReader(_, _) => ResourceKind::LineReader,
+      Repr::DnsQuery(_, _, _) => ResourceKind::DnsQuery,
     }
   }
 }
 
-impl Write for Resource {
-  fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
-    unimplemented!()
-  }
+pub fn table_entries() -> Vec<(ResourceId, ResourceKind)> {
+  kind_index()
+    .iter()
+    .map(|(rid, kind)| (*rid, *kind))
+    .collect()
+}
 
-  fn flush(&mut self) -> std::io::Result<()> {
-    unimplemented!()
-  }
+/// The number of bytes read from and written to all open resources, as
+/// `(bytes_read, bytes_written)`. Closed resources no longer count.
+pub fn global_io_totals() -> (u64, u64) {
+  let totals = RESOURCE_TABLE.lock().unwrap().io_totals();
+  (totals.bytes_read, totals.bytes_written)
 }
 
-impl AsyncWrite for Resource {
-  fn poll_write(&mut self, buf: &[u8]) -> Poll<usize, Error> {
-    let mut table = RESOURCE_TABLE.lock().unwrap();
-    let maybe_repr = table.get_mut(&self.rid);
-    match maybe_repr {
-      None => panic!("bad rid"),
-      Some(repr) => match repr {
-        Repr::FsFile(ref mut f) => f.poll_write(buf),
-        Repr::Stdout(ref mut f) => f.poll_write(buf),
-        Repr::Stderr(ref mut f) => f.poll_write(buf),
-        Repr::TcpStream(ref mut f) => f.poll_write(buf),
-        Repr::ChildStdin(ref mut f) => f.poll_write(buf),
-        _ => panic!("Cannot write"),
-      },
-    }
-  }
+#[test]
+fn test_io_totals() {
+  let mut table = ResourceTable::new();
+  let (reader_a, writer_a) = mem_pipe::pipe(1);
+  let (_reader_b, writer_b) = mem_pipe::pipe(1);
+  let a = insert(&mut table, Repr::MemPipeWriter(writer_a)).unwrap();
+  let b = insert(&mut table, Repr::MemPipeWriter(writer_b)).unwrap();
+  let r = insert(&mut table, Repr::MemPipeReader(reader_a)).unwrap();
+  // A dup'd rid shares the counters of its entry.
+  let a_dup = new_rid().unwrap();
+  table.dup(a, a_dup).unwrap();
+  let mut buf = [0u8; 2];
+  futures::future::lazy(|| -> Result<(), Error> {
+    assert_eq!(poll_write_in(&mut table, a, b"ab")?, Async::Ready(2));
+    assert_eq!(poll_write_in(&mut table, a_dup, b"c")?, Async::Ready(1));
+    assert_eq!(poll_write_in(&mut table, b, b"defg")?, Async::Ready(4));
+    assert_eq!(poll_read_in(&mut table, r, &mut buf)?, Async::Ready(2));
+    Ok(())
+  }).wait()
+  .unwrap();
+  assert_eq!(&buf, b"ab");
+  let totals = table.io_totals();
+  assert_eq!(totals.bytes_written, 3 + 4);
+  assert_eq!(totals.bytes_read, 2);
 
-  fn shutdown(&mut self) -> futures::Poll<(), std::io::Error> {
-    unimplemented!()
-  }
+  // Other tests open and close resources concurrently, so the global totals
+  // can only be checked loosely.
+  let (reader, writer) = new_pipe().unwrap();
+  tokio::io::write_all(writer.clone(), b"hello")
+    .wait()
+    .unwrap();
+  assert!(global_io_totals().1 >= 5);
+  reader.close();
+  writer.close();
 }
 
-fn new_rid() -> ResourceId {
-  let next_rid = NEXT_RID.fetch_add(1, Ordering::SeqCst);
-  next_rid as ResourceId
+/// Returns the rids of all open resources of the given kind, e.g. to close
+/// every socket on shutdown.
+pub fn table_entries_by_kind(kind: ResourceKind) -> Vec<ResourceId> {
+  kind_index()
+    .iter()
+    .filter(|(_, k)| **k == kind)
+    .map(|(rid, _)| *rid)
+    .collect()
 }
 
-pub fn add_fs_file(fs_file: tokio::fs::File) -> Resource {
-  let rid = new_rid();
-  let mut tg = RESOURCE_TABLE.lock().unwrap();
-  match tg.insert(rid, Repr::FsFile(fs_file)) {
-    Some(_) => panic!("There is already a file with that rid"),
-    None => Resource { rid },
-  }
-}
+#[test]
+fn test_table_entries_by_kind() {
+  let addr = "127.0.0.1:0".parse().unwrap();
+  let listener1 =
+    add_tcp_listener(tokio::net::TcpListener::bind(&addr).unwrap()).unwrap();
+  let listener2 =
+    add_tcp_listener(tokio::net::TcpListener::bind(&addr).unwrap()).unwrap();
+  let file = tempfile::tempfile().unwrap();
+  let file = add_fs_file(tokio::fs::File::from_std(file)).unwrap();
 
-pub fn add_tcp_listener(listener: tokio::net::TcpListener) -> Resource {
-  let rid = new_rid();
-  let mut tg = RESOURCE_TABLE.lock().unwrap();
-  let r = tg.insert(rid, Repr::TcpListener(listener, None));
-  assert!(r.is_none());
-  Resource { rid }
+  // Other tests may have listeners open at the same time.
+  let rids = table_entries_by_kind(ResourceKind::TcpListener);
+  assert!(rids.contains(&listener1.rid));
+  assert!(rids.contains(&listener2.rid));
+  assert!(!rids.contains(&file.rid));
+  assert!(table_entries_by_kind(ResourceKind::Stdin).contains(&0));
+
+  listener1.close();
+  listener2.close();
+  file.close();
 }
 
-pub fn add_tcp_stream(stream: tokio::net::TcpStream) -> Resource {
-  let rid = new_rid();
-  let mut tg = RESOURCE_TABLE.lock().unwrap();
-  let r = tg.insert(rid, Repr::TcpStream(stream));
-  assert!(r.is_none());
-  Resource { rid }
+/// Like `table_entries()`, but also includes the IO counters of each resource.
+pub fn table_entries_with_stats(
+) -> Vec<(ResourceId, ResourceKind, ResourceStats)> {
+  let table = RESOURCE_TABLE.lock().unwrap();
+
+  table
+    .iter_entries()
+    .map(|(key, entry)| (*key, ResourceKind::from(&entry.repr), entry.stats()))
+    .collect()
 }
 
-pub fn add_hyper_body(body: hyper::Body) -> Resource {
-  let rid = new_rid();
-  let mut tg = RESOURCE_TABLE.lock().unwrap();
-  let body = HttpBody::from(body);
-  let r = tg.insert(rid, Repr::HttpBody(body));
-  assert!(r.is_none());
-  Resource { rid }
+#[test]
+fn test_table_entries() {
+  let mut entries = table_entries();
+  entries.sort_by_key(|(rid, _)| *rid);
+  assert_eq!(entries[0], (0, ResourceKind::Stdin));
+  assert_eq!(entries[1], (1, ResourceKind::Stdout));
+  assert_eq!(entries[2], (2, ResourceKind::Stderr));
+  assert_eq!(entries[0].1.to_string(), "stdin");
 }
 
-pub fn add_repl(repl: Repl) -> Resource {
-  let rid = new_rid();
-  let mut tg = RESOURCE_TABLE.lock().unwrap();
-  let r = tg.insert(rid, Repr::Repl(Arc::new(Mutex::new(repl))));
-  assert!(r.is_none());
-  Resource { rid }
+fn inspect_repr(repr: &Repr) -> String {
+  String::from(ResourceKind::from(repr).as_str())
 }
 
-pub fn add_worker(wc: WorkerChannels) -> Resource {
-  let rid = new_rid();
-  let mut tg = RESOURCE_TABLE.lock().unwrap();
-  let r = tg.insert(rid, Repr::Worker(wc));
-  assert!(r.is_none());
-  Resource { rid }
+/// Like `table_entries()`, but describes each resource for debugging: sockets
+/// include their `local->peer` addresses and files the id of their handle,
+/// which is shared by dup'd rids. Other resources are described by their kind
+/// only.
+pub fn table_entries_detailed() -> Vec<(ResourceId, String)> {
+  let table = RESOURCE_TABLE.lock().unwrap();
+
+  table
+    .rids
+    .iter()
+    .map(|(rid, key)| (*rid, describe_repr(*key, &table.entries[key].repr)))
+    .collect()
 }
 
-pub fn worker_post_message(
-  rid: ResourceId,
-  buf: Buf,
-) -> futures::sink::Send<futures::sync::mpsc::Sender<Buf>> {
-  let mut table = RESOURCE_TABLE.lock().unwrap();
-  let maybe_repr = table.get_mut(&rid);
-  match maybe_repr {
-    Some(Repr::Worker(ref mut wc)) => {
-      // unwrap here is incorrect, but doing it anyway
-      wc.0.clone().send(buf)
-    }
-    _ => panic!("bad resource"), // futures::future::err(bad_resource()).into(),
+fn describe_repr(key: ResourceId, repr: &Repr) -> String {
+  let kind = inspect_repr(repr);
+  match repr {
+    Repr::FsFile(_) => format!("{} #{}", kind, key),
+    Repr::TcpListener(ref s, _) => match s.local_addr() {
+      Ok(addr) => format!("{} {}", kind, addr),
+      Err(_) => kind,
+    },
+    Repr::UdpSocket(ref s) => match s.local_addr() {
+      Ok(addr) => format!("{} {}", kind, addr),
+      Err(_) => kind,
+    },
+    Repr::TcpStream(ref s) => describe_stream(kind, s),
+    Repr::TlsStream(ref s) => describe_stream(kind, s.get_ref().0),
+    Repr::ServerTlsStream(ref s) => describe_stream(kind, s.get_ref().0),
+    _ => kind,
   }
 }
 
-pub struct WorkerReceiver {
-  rid: ResourceId,
+fn describe_stream(kind: String, s: &TcpStream) -> String {
+  match (s.local_addr(), s.peer_addr()) {
+    (Ok(local), Ok(peer)) => format!("{} {}->{}", kind, local, peer),
+    _ => kind,
+  }
 }
 
-// Invert the dumbness that tokio_process causes by making Child itself a future.
-impl Future for WorkerReceiver {
-  type Item = Option<Buf>;
-  type Error = DenoError;
+#[test]
+fn test_table_entries_detailed() {
+  let (local, peer) = tcp_loopback_pair();
+  let peer_port = local.peer_addr().unwrap().port();
+  let stream = add_tcp_stream(local).unwrap();
+  let file = tempfile::tempfile().unwrap();
+  let file = add_fs_file(tokio::fs::File::from_std(file)).unwrap();
+  let file_dup = dup(file.rid).unwrap();
 
-  fn poll(&mut self) -> Poll<Option<Buf>, DenoError> {
-    let mut table = RESOURCE_TABLE.lock().unwrap();
-    let maybe_repr = table.get_mut(&self.rid);
-    match maybe_repr {
-      Some(Repr::Worker(ref mut wc)) => wc.1.poll().map_err(|()| {
-        errors::new(errors::ErrorKind::Other, "recv msg error".to_string())
-      }),
-      _ => Err(bad_resource()),
+  let entries: HashMap<ResourceId, String> =
+    table_entries_detailed().into_iter().collect();
+  assert_eq!(entries[&0], "stdin");
+  assert_eq!(entries[&1], "stdout");
+  assert_eq!(entries[&2], "stderr");
+  let stream_info = &entries[&stream.rid];
+  assert!(stream_info.starts_with("tcpStream 127.0.0.1:"));
+  assert!(stream_info.ends_with(&format!("->127.0.0.1:{}", peer_port)));
+  assert!(entries[&file.rid].starts_with("fsFile #"));
+  assert_eq!(entries[&file.rid], entries[&file_dup]);
+
+  stream.close();
+  file.close();
+  close(file_dup).unwrap();
+  drop(peer);
+}
+
+/// Describes every open resource as a JSON object keyed by rid. Each value has
+/// `kind`, `bytesRead` and `bytesWritten`, and sockets also have `localAddr`
+/// and, once connected, `peerAddr`.
+pub fn table_entries_json() -> serde_json::Value {
+  let table = RESOURCE_TABLE.lock().unwrap();
+  let mut entries = serde_json::Map::new();
+  for (rid, key) in table.rids.iter() {
+    let entry = &table.entries[key];
+    let stats = entry.stats();
+    let mut info = json!({
+      "kind": inspect_repr(&entry.repr),
+      "bytesRead": stats.bytes_read,
+      "bytesWritten": stats.bytes_written,
+    });
+    let (local_addr, peer_addr) = repr_addrs(&entry.repr);
+    if let Some(addr) = local_addr {
+      info["localAddr"] = json!(addr.to_string());
     }
+    if let Some(addr) = peer_addr {
+      info["peerAddr"] = json!(addr.to_string());
+    }
+    entries.insert(rid.to_string(), info);
   }
+  serde_json::Value::Object(entries)
 }
 
-pub fn worker_recv_message(rid: ResourceId) -> WorkerReceiver {
-  WorkerReceiver { rid }
+// The local and peer addresses of a socket resource.
+fn repr_addrs(repr: &Repr) -> (Option<SocketAddr>, Option<SocketAddr>) {
+  match repr {
+    Repr::TcpListener(ref s, _) => (s.local_addr().ok(), None),
+    Repr::UdpSocket(ref s) => (s.local_addr().ok(), None),
+    Repr::TcpStream(ref s) => stream_addrs(s),
+    Repr::TlsStream(ref s) => stream_addrs(s.get_ref().0),
+    Repr::ServerTlsStream(ref s) => stream_addrs(s.get_ref().0),
+    _ => (None, None),
+  }
+}
+
+fn stream_addrs(s: &TcpStream) -> (Option<SocketAddr>, Option<SocketAddr>) {
+  (s.local_addr().ok(), s.peer_addr().ok())
+}
+
+#[test]
+fn test_table_entries_json() {
+  let (local, peer) = tcp_loopback_pair();
+  let peer_addr = local.peer_addr().unwrap().to_string();
+  let stream = add_tcp_stream(local).unwrap();
+
+  let entries = table_entries_json();
+  assert_eq!(entries["0"]["kind"], "stdin");
+  assert_eq!(entries["1"]["kind"], "stdout");
+  assert_eq!(entries["2"]["kind"], "stderr");
+  assert!(entries["0"]["bytesRead"].is_u64());
+  assert!(entries["0"].get("localAddr").is_none());
+  let info = &entries[&stream.rid.to_string()];
+  assert_eq!(info["kind"], "tcpStream");
+  assert_eq!(info["bytesWritten"], 0);
+  assert!(info["localAddr"]
+    .as_str()
+    .unwrap()
+    .starts_with("127.0.0.1:"));
+  assert_eq!(info["peerAddr"], json!(peer_addr));
+
+  stream.close();
+  drop(peer);
+}
+
+// Runs `f` on the std::fs::File of the FsFile resource `rid` inside
+// tokio_threadpool::blocking(). tokio::fs::File does not expose its fd, so it
+// is taken out of the table and put back while the caller holds the lock.
+fn fs_file_blocking<F, T>(
+  table: &mut ResourceTable,
+  rid: &ResourceId,
+  f: F,
+) -> Poll<T, Error>
+where
+  F: FnOnce(&std::fs::File) -> std::io::Result<T>,
+{
+  let result = table
+    .map_repr(rid, |repr| match repr {
+      Repr::FsFile(fs_file) => {
+        let std_file = fs_file.into_std();
+        let result = tokio_threadpool::blocking(|| f(&std_file));
+        (Repr::FsFile(tokio::fs::File::from_std(std_file)), result)
+      }
+      _ => unreachable!(),
+    }).unwrap();
+  match result {
+    Ok(Async::Ready(Ok(t))) => Ok(Async::Ready(t)),
+    Ok(Async::Ready(Err(err))) => Err(err),
+    Ok(Async::NotReady) => Ok(Async::NotReady),
+    Err(err) => Err(Error::new(std::io::ErrorKind::Other, err.to_string())),
+  }
+}
+
+// writev() only takes up to IOV_MAX buffers. Like std, pass at most 1024
+// and let the caller write the rest after the short write.
+#[cfg(unix)]
+const MAX_IOVECS: usize = 1024;
+
+// Writes `bufs` to `fd` with a single writev() call.
+#[cfg(unix)]
+fn writev(
+  fd: std::os::unix::io::RawFd,
+  bufs: &[&[u8]],
+) -> std::io::Result<usize> {
+  let iovecs: Vec<libc::iovec> = bufs
+    .iter()
+    .take(MAX_IOVECS)
+    .map(|buf| libc::iovec {
+      iov_base: buf.as_ptr() as *mut libc::c_void,
+      iov_len: buf.len(),
+    }).collect();
+  let n =
+    unsafe { libc::writev(fd, iovecs.as_ptr(), iovecs.len() as libc::c_int) };
+  if n < 0 {
+    return Err(Error::last_os_error());
+  }
+  Ok(n as usize)
+}
+
+// Like writev(), but reads into `bufs` with a single readv() call.
+#[cfg(unix)]
+fn readv(
+  fd: std::os::unix::io::RawFd,
+  bufs: &mut [&mut [u8]],
+) -> std::io::Result<usize> {
+  let iovecs: Vec<libc::iovec> = bufs
+    .iter_mut()
+    .take(MAX_IOVECS)
+    .map(|buf| libc::iovec {
+      iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+      iov_len: buf.len(),
+    }).collect();
+  let n =
+    unsafe { libc::readv(fd, iovecs.as_ptr(), iovecs.len() as libc::c_int) };
+  if n < 0 {
+    return Err(Error::last_os_error());
+  }
+  Ok(n as usize)
+}
+
+// Like eager_unix::tcp_write(): attempt writev() on the raw fd and only
+// defer to tokio, which registers interest in writability, on EWOULDBLOCK.
+#[cfg(unix)]
+fn tcp_write_vectored(
+  tcp_stream: &mut TcpStream,
+  bufs: &[&[u8]],
+) -> Poll<usize, Error> {
+  use std::os::unix::io::AsRawFd;
+  match writev(tcp_stream.as_raw_fd(), bufs) {
+    Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+      match bufs.iter().find(|buf| !buf.is_empty()) {
+        Some(buf) => tcp_stream.poll_write(buf),
+        None => Ok(Async::Ready(0)),
+      }
+    }
+    r => r.map(Async::Ready),
+  }
+}
+
+// Like tcp_write_vectored(), but for readv().
+#[cfg(unix)]
+fn tcp_read_vectored(
+  tcp_stream: &mut TcpStream,
+  bufs: &mut [&mut [u8]],
+) -> Poll<usize, Error> {
+  use std::os::unix::io::AsRawFd;
+  match readv(tcp_stream.as_raw_fd(), bufs) {
+    Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+      match bufs.iter_mut().find(|buf| !buf.is_empty()) {
+        Some(buf) => tcp_stream.poll_read(buf),
+        None => Ok(Async::Ready(0)),
+      }
+    }
+    r => r.map(Async::Ready),
+  }
+}
+
+#[test]
+fn test_poll_read_vectored() {
+  let (local, peer) = tcp_loopback_pair();
+  let mut resource = add_tcp_stream(local).unwrap();
+  tokio::io::write_all(peer, b"0123456789").wait().unwrap();
+  let mut a = [0u8; 5];
+  let mut b = [0u8; 5];
+  let n = futures::future::poll_fn(|| {
+    let mut bufs: [&mut [u8]; 2] = [&mut a, &mut b];
+    resource.poll_read_vectored(&mut bufs)
+  }).wait()
+  .unwrap();
+  assert_eq!(n, 10);
+  assert_eq!(&a, b"01234");
+  assert_eq!(&b, b"56789");
+  assert_eq!(resource.stats().unwrap().bytes_read, 10);
+  resource.close();
+}
+
+#[test]
+fn test_poll_read_vectored_sequential() {
+  let (mut reader, mut writer) = new_pipe().unwrap();
+  futures::future::poll_fn(|| writer.poll_write(b"0123456789"))
+    .wait()
+    .unwrap();
+  let mut a = [0u8; 5];
+  let mut b = [0u8; 5];
+  let n = futures::future::poll_fn(|| {
+    let mut bufs: [&mut [u8]; 2] = [&mut a, &mut b];
+    reader.poll_read_vectored(&mut bufs)
+  }).wait()
+  .unwrap();
+  assert_eq!(n, 10);
+  assert_eq!(&a, b"01234");
+  assert_eq!(&b, b"56789");
+  reader.close();
+  writer.close();
+}
+
+#[test]
+fn test_poll_write_vectored() {
+  let (local, peer) = tcp_loopback_pair();
+  let mut resource = add_tcp_stream(local).unwrap();
+  let bufs: [&[u8]; 4] = [b"GET / HTTP/1.1\r\n", b"", b"\r\n", b"body"];
+  let mut expected = Vec::new();
+  for buf in bufs.iter() {
+    expected.extend_from_slice(buf);
+  }
+  // The socket buffer has plenty of room, so all slices go out at once.
+  let nwritten =
+    futures::future::poll_fn(|| resource.poll_write_vectored(&bufs))
+      .wait()
+      .unwrap();
+  assert_eq!(nwritten, expected.len());
+  assert_eq!(
+    resource.stats().unwrap().bytes_written,
+    expected.len() as u64
+  );
+  resource.close();
+  let peer = add_tcp_stream(peer).unwrap();
+  let (peer, received) = poll_read_to_end(peer).wait().unwrap();
+  assert_eq!(received, expected);
+  peer.close();
+}
+
+#[test]
+fn test_poll_write_vectored_fs_file() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("write_vectored.txt");
+  let std_file = std::fs::File::create(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  tokio_util::init(|| {
+    let mut r = resource.clone();
+    let n = tokio_util::block_on(futures::future::poll_fn(move || {
+      let bufs: [&[u8]; 3] = [b"ab", b"cd", b"e"];
+      r.poll_write_vectored(&bufs)
+    })).unwrap();
+    assert_eq!(n, 5);
+    resource.close();
+  });
+  assert_eq!(std::fs::read(&path).unwrap(), b"abcde");
+}
+
+#[test]
+fn test_poll_write_vectored_sequential() {
+  let (reader, mut writer) = new_pipe().unwrap();
+  let bufs: [&[u8]; 3] = [b"ab", b"cd", b"e"];
+  let n = futures::future::poll_fn(|| writer.poll_write_vectored(&bufs))
+    .wait()
+    .unwrap();
+  assert_eq!(n, 5);
+  writer.close();
+  let (reader, data) = poll_read_to_end(reader).wait().unwrap();
+  assert_eq!(data, b"abcde");
+  reader.close();
+}
+
+// The io::Error equivalent of bad_resource(), for the AsyncRead/AsyncWrite
+// paths. Returned when JS passes a rid that is closed or was never open.
+fn bad_rid_io_error() -> std::io::Error {
+  std::io::Error::new(std::io::ErrorKind::NotFound, "bad resource id")
+}
+
+/// A single directory entry, as yielded by `Resource::poll_read_dir()`.
+#[derive(Debug)]
+pub struct DirEntryInfo {
+  pub name: OsString,
+  pub file_type: std::fs::FileType,
+  pub len: u64,
+}
+
+/// Metadata of an open FsFile, as returned by `Resource::poll_metadata()`.
+/// Times are `None` where the platform doesn't record them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileMetadata {
+  pub len: u64,
+  pub modified: Option<SystemTime>,
+  pub accessed: Option<SystemTime>,
+  pub readonly: bool,
+}
+
+// Abstract async file interface.
+// Ideally in unix, if Resource represents an OS rid, it will be the same.
+#[derive(Clone, Debug)]
+pub struct Resource {
+  pub rid: ResourceId,
+}
+
+/// A connection accepted by `Resource::poll_accept()`.
+#[derive(Debug)]
+pub enum Accepted {
+  Tcp(TcpStream, SocketAddr),
+  // Unix streams are registered as a new resource right away.
+  #[cfg(unix)]
+  Unix(Resource, std::os::unix::net::SocketAddr),
+}
+
+impl Resource {
+  // TODO Should it return a Resource instead of net::TcpStream?
+  pub fn poll_accept(&mut self) -> Poll<Accepted, Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      None => Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "Listener has been closed",
+      )),
+      Some(repr) => match repr {
+        Repr::TcpListener(ref mut s, _) => {
+          let (stream, addr) = try_ready!(s.poll_accept());
+          Ok(Accepted::Tcp(stream, addr).into())
+        }
+        #[cfg(unix)]
+        Repr::UnixListener(ref mut s) => {
+          let (stream, addr) = try_ready!(s.poll_accept());
+          let rid =
+            insert(&mut table, Repr::UnixStream(stream)).map_err(|e| {
+              Error::new(std::io::ErrorKind::Other, e.to_string())
+            })?;
+          Ok(Accepted::Unix(Resource { rid }, addr).into())
+        }
+        _ => panic!("Cannot accept"),
+      },
+    }
+  }
+
+  /// Resolves when a timer resource fires. Fails if the timer is closed first.
+  pub fn poll_timer(&mut self) -> Poll<(), DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::Timer(ref mut delay, ref mut task)) => {
+        let r = delay.poll().map_err(timer_error);
+        if let Ok(Async::NotReady) = r {
+          task.replace(futures::task::current());
+        }
+        r
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Resolves with the addresses found by a DNS query resource. The lookup
+  /// blocks the threadpool thread that polls it, so this must be polled on
+  /// the tokio threadpool. Fails if the query is closed before the poll that
+  /// does the lookup; closing the query doesn't interrupt a lookup that is
+  /// already running, but its result is discarded.
+  pub fn poll_dns(&mut self) -> Poll<Vec<SocketAddr>, DenoError> {
+    let (host, port) = {
+      let table = RESOURCE_TABLE.lock().unwrap();
+      match table.get(&self.rid) {
+        Some(Repr::DnsQuery(ref host, port, _)) => (host.clone(), *port),
+        _ => return Err(bad_resource()),
+      }
+    };
+    let r = tokio_threadpool::blocking(|| {
+      (host.as_str(), port)
+        .to_socket_addrs()
+        .map(|addrs| addrs.collect::<Vec<_>>())
+    });
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let task = match table.get_mut(&self.rid) {
+      Some(Repr::DnsQuery(_, _, ref mut task)) => task,
+      // Closed while looking it up.
+      _ => return Err(bad_resource()),
+    };
+    match r {
+      Ok(Async::Ready(addrs)) => Ok(Async::Ready(addrs?)),
+      // The threadpool has no room for another blocking section; it wakes
+      // the task once it does.
+      Ok(Async::NotReady) => {
+        task.replace(futures::task::current());
+        Ok(Async::NotReady)
+      }
+      Err(err) => Err(errors::new(errors::ErrorKind::Other, err.to_string())),
+    }
+  }
+
+  /// Resolves on every tick of an interval resource. Fails once the interval
+  /// is closed.
+  pub fn poll_interval(&mut self) -> Poll<(), DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::Interval(ref mut interval, ref mut task)) => {
+        match interval.poll().map_err(timer_error)? {
+          Async::Ready(_) => Ok(Async::Ready(())),
+          Async::NotReady => {
+            task.replace(futures::task::current());
+            Ok(Async::NotReady)
+          }
+        }
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Resolves with the next change seen by a fs watcher resource.
+  #[cfg(target_os = "linux")]
+  pub fn poll_fs_event(&mut self) -> Poll<FsEvent, DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::FsWatcher(ref mut watcher)) => {
+        watcher.poll_event().map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Resolves once per delivered signal on a signal resource, with the signal
+  /// number.
+  #[cfg(unix)]
+  pub fn poll_signal(&mut self) -> Poll<Option<i32>, Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::Signal(ref mut signal)) => signal.poll(),
+      _ => Err(bad_rid_io_error()),
+    }
+  }
+
+  /// Like `poll_accept()`, but registers the accepted TcpStream as a new
+  /// resource under the same table lock, and returns its rid.
+  pub fn poll_accept_resource(
+    &mut self,
+  ) -> Poll<(ResourceId, SocketAddr), DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let (stream, addr) = match table.get_mut(&self.rid) {
+      None => {
+        return Err(errors::new(
+          errors::ErrorKind::Other,
+          String::from("Listener has been closed"),
+        ))
+      }
+      Some(Repr::TcpListener(ref mut s, _)) => try_ready!(s.poll_accept()),
+      _ => return Err(bad_resource()),
+    };
+    let rid = insert(&mut table, Repr::TcpStream(stream))?;
+    Ok((rid, addr).into())
+  }
+
+  /// Like `poll_accept_resource()`, but drains up to `max` ready connections
+  /// in one go. Only returns `NotReady` if no connection is ready. A failure
+  /// to accept after some connections were accepted ends the batch early, and
+  /// the error is reported by the next poll. Running out of room in the table
+  /// ends the batch before another connection is accepted. If adding an
+  /// accepted connection fails anyway, the connections of the batch are
+  /// closed and the error is returned right away.
+  pub fn poll_accept_batch(
+    &mut self,
+    max: usize,
+  ) -> Poll<Vec<(ResourceId, SocketAddr)>, DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let mut accepted: Vec<(ResourceId, SocketAddr)> = Vec::new();
+    while accepted.len() < max {
+      let r = table.check_max_resources().and_then(|_| {
+        match table.get_mut(&self.rid) {
+          None => Err(errors::new(
+            errors::ErrorKind::Other,
+            String::from("Listener has been closed"),
+          )),
+          Some(Repr::TcpListener(ref mut s, _)) => {
+            s.poll_accept().map_err(DenoError::from)
+          }
+          _ => Err(bad_resource()),
+        }
+      });
+      match r {
+        Ok(Async::Ready((stream, addr))) => {
+          match insert(&mut table, Repr::TcpStream(stream)) {
+            Ok(rid) => accepted.push((rid, addr)),
+            Err(e) => {
+              for (rid, _) in accepted {
+                table.remove(&rid);
+              }
+              return Err(e);
+            }
+          }
+        }
+        Ok(Async::NotReady) => break,
+        Err(e) => {
+          if accepted.is_empty() {
+            return Err(e);
+          }
+          break;
+        }
+      }
+    }
+    if accepted.is_empty() && max > 0 {
+      return Ok(Async::NotReady);
+    }
+    Ok(accepted.into())
+  }
+
+  /// Checks whether the peer of a TcpStream resource is still connected,
+  /// without consuming any data. Returns false once the peer has closed or
+  /// reset the connection, and true if data is pending or the connection is
+  /// merely idle.
+  #[cfg(unix)]
+  pub fn connection_alive(&self) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    let table = RESOURCE_TABLE.lock().unwrap();
+    let fd = match table.get(&self.rid) {
+      Some(Repr::TcpStream(ref s)) => s.as_raw_fd(),
+      _ => return Err(bad_rid_io_error()),
+    };
+    // Peek at the socket directly rather than through tokio, so that no task
+    // is registered for readability and a reader of the resource doesn't
+    // lose its wakeup. A zero-length peek can't tell EOF from no data, so
+    // peek one byte.
+    let mut buf = [0u8; 1];
+    let n = unsafe {
+      libc::recv(
+        fd,
+        buf.as_mut_ptr() as *mut libc::c_void,
+        1,
+        libc::MSG_PEEK | libc::MSG_DONTWAIT,
+      )
+    };
+    if n >= 0 {
+      return Ok(n > 0);
+    }
+    let err = Error::last_os_error();
+    match err.kind() {
+      std::io::ErrorKind::WouldBlock => Ok(true),
+      std::io::ErrorKind::ConnectionReset => Ok(false),
+      _ => Err(err),
+    }
+  }
+
+  /// Resolves once reading from the resource would make progress, without
+  /// reading anything. Supported for TcpStream and mem pipe readers; FsFile
+  /// resources are always ready.
+  pub fn poll_read_ready(&mut self) -> Poll<(), Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      None => Err(bad_rid_io_error()),
+      // Asks the reactor instead of peeking, so that nothing is read from the
+      // socket. EOF and errors count as readable too.
+      Some(Repr::TcpStream(ref s)) => {
+        try_ready!(s.poll_read_ready(mio::Ready::readable()));
+        Ok(Async::Ready(()))
+      }
+      Some(Repr::MemPipeReader(ref mut r)) => r.poll_read_ready(),
+      Some(Repr::FsFile(_)) => Ok(Async::Ready(())),
+      Some(_) => Err(Error::new(
+        std::io::ErrorKind::Other,
+        "Readiness can't be polled for this resource",
+      )),
+    }
+  }
+
+  /// Accepts connections on a TcpListener resource until `deadline`, at
+  /// which point the stream ends. Connections are registered like with
+  /// `poll_accept_resource()`. The listener is left open.
+  pub fn accept_until(
+    &self,
+    deadline: Instant,
+  ) -> impl Stream<Item = (ResourceId, SocketAddr), Error = DenoError> {
+    let mut listener = self.clone();
+    let mut delay = tokio::timer::Delay::new(deadline);
+    futures::stream::poll_fn(move || {
+      if let Async::Ready(()) = delay.poll().map_err(timer_error)? {
+        return Ok(Async::Ready(None));
+      }
+      let conn = try_ready!(listener.poll_accept_resource());
+      Ok(Async::Ready(Some(conn)))
+    })
+  }
+
+  /// Track the current task (for TcpListener resource).
+  /// Throws an error if another task is already tracked.
+  pub fn track_task(&mut self) -> Result<(), std::io::Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    // Only track if is TcpListener.
+    if let Some(Repr::TcpListener(_, t)) = table.get_mut(&self.rid) {
+      // Currently, we only allow tracking a single accept task for a listener.
+      // This might be changed in the future with multiple workers.
+      // Caveat: TcpListener by itself also only tracks an accept task at a time.
+      // See https://github.com/tokio-rs/tokio/issues/846#issuecomment-454208883
+      if t.is_some() {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::Other,
+          "Another accept task is ongoing",
+        ));
+      }
+      t.replace(futures::task::current());
+    }
+    Ok(())
+  }
+
+  /// Stop tracking a task (for TcpListener resource).
+  /// Happens when the task is done and thus no further tracking is needed.
+  pub fn untrack_task(&mut self) {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    // Only untrack if is TcpListener.
+    if let Some(Repr::TcpListener(_, t)) = table.get_mut(&self.rid) {
+      // DO NOT assert is_some here.
+      // See reasoning in Accept::poll().
+      t.take();
+    }
+  }
+
+  pub fn close(&self) {
+    let r = close(self.rid);
+    assert!(r.is_ok());
+  }
+
+  /// The declared length of a HttpBody resource, if the sender provided one.
+  pub fn http_content_length(&self) -> DenoResult<Option<u64>> {
+    let table = RESOURCE_TABLE.lock().unwrap();
+    match table.get(&self.rid) {
+      Some(Repr::HttpBody(ref body)) => Ok(body.content_length()),
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Makes reads of a HttpBody resource to the end allocate `bytes` up
+  /// front instead of growing the buffer as data arrives. By default the
+  /// declared length is used.
+  pub fn http_hint_capacity(&mut self, bytes: usize) -> DenoResult<()> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::HttpBody(ref mut body)) => {
+        body.hint_capacity(bytes);
+        Ok(())
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// The number of bytes reads of a HttpBody resource to the end allocate up
+  /// front, see `http_hint_capacity()`.
+  pub fn http_capacity_hint(&self) -> DenoResult<usize> {
+    let table = RESOURCE_TABLE.lock().unwrap();
+    match table.get(&self.rid) {
+      Some(Repr::HttpBody(ref body)) => Ok(body.capacity_hint()),
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Polls for the trailer headers of a HttpBody resource. Trailers are only
+  /// available after the whole body has been read.
+  pub fn poll_trailers(&mut self) -> Poll<Option<hyper::HeaderMap>, DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::HttpBody(ref mut body)) => {
+        body.poll_trailers().map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Reads a HttpBody or FsFile resource to the end, failing with `TooLarge`
+  /// once more than `limit` bytes have been read. Nothing read so far is
+  /// returned in that case.
+  pub fn read_to_end_capped(&self, limit: usize) -> ReadToEndCapped {
+    ReadToEndCapped {
+      resource: self.clone(),
+      buf: Vec::new(),
+      limit,
+      checked: false,
+    }
+  }
+
+  /// Writes the history of a repl resource to its history file.
+  pub fn save_repl_history(&self) -> DenoResult<()> {
+    let table = RESOURCE_TABLE.lock().unwrap();
+    match table.get(&self.rid) {
+      Some(Repr::Repl(ref r)) => r.lock().unwrap().save_history(),
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Returns how many bytes have been read from and written to this resource
+  /// so far. The counters are shared with any rid dup'd from this one.
+  pub fn stats(&self) -> DenoResult<ResourceStats> {
+    let table = RESOURCE_TABLE.lock().unwrap();
+    match table.entry(&self.rid) {
+      None => Err(bad_resource()),
+      Some(entry) => Ok(entry.stats()),
+    }
+  }
+
+  /// Resolves once this rid is closed, E.G. to stop an accept loop when
+  /// another part of the program closes the listener. Resolves right away if
+  /// the rid is not open.
+  pub fn on_close(&self) -> impl Future<Item = (), Error = ()> {
+    let (tx, rx) = oneshot::channel();
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    if table.contains_key(&self.rid) {
+      let waiters = table.close_waiters.entry(self.rid).or_default();
+      // Forget the futures that were dropped without the rid being closed,
+      // so that a long-lived rid doesn't accumulate them.
+      waiters.retain(|waiter| !waiter.is_canceled());
+      waiters.push(tx);
+    }
+    // If the sender was dropped, the rid was not open.
+    rx.then(|_| Ok(()))
+  }
+
+  /// Reads into `buf` like `eager_read()`, but fails with a `TimedOut` error
+  /// if nothing arrives within `dur`. The resource is left open either way.
+  pub fn read_timeout<T>(
+    &self,
+    buf: T,
+    dur: Duration,
+  ) -> impl Future<Item = (T, usize), Error = DenoError>
+  where
+    T: AsMut<[u8]>,
+  {
+    tokio::timer::Timeout::new(eager_read(self.clone(), buf), dur).then(|r| {
+      match r {
+        Ok((_, buf, nread)) => Ok((buf, nread)),
+        Err(ref err) if err.is_elapsed() => Err(errors::new(
+          errors::ErrorKind::TimedOut,
+          String::from("read timed out"),
+        )),
+        Err(err) => match err.into_inner() {
+          Some(err) => Err(DenoError::from(err)),
+          None => Err(errors::new(
+            errors::ErrorKind::Other,
+            String::from("timer error while reading"),
+          )),
+        },
+      }
+    })
+  }
+
+  /// Tells what the resource supports, so ops can validate a rid before
+  /// attempting IO on it.
+  pub fn capabilities(&self) -> DenoResult<ResourceCaps> {
+    resource_kind(self.rid)
+      .map(ResourceKind::capabilities)
+      .ok_or_else(bad_resource)
+  }
+
+  /// Whether the resource refers to a terminal. Stdio resources check the
+  /// process' stdio streams, FsFile resources their file descriptor (e.g. an
+  /// opened /dev/tty). Sockets, pipes and all other resources never are.
+  pub fn is_tty(&self) -> DenoResult<bool> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let stream = match table.get(&self.rid) {
+      None => return Err(bad_resource()),
+      Some(Repr::Stdin(_)) => atty::Stream::Stdin,
+      Some(Repr::Stdout(_)) => atty::Stream::Stdout,
+      Some(Repr::Stderr(_)) => atty::Stream::Stderr,
+      Some(Repr::FsFile(_)) => {
+        let is_tty = table.map_repr(&self.rid, |repr| match repr {
+          Repr::FsFile(f) => {
+            let std_file = f.into_std();
+            let is_tty = file_is_tty(&std_file);
+            (Repr::FsFile(tokio::fs::File::from_std(std_file)), is_tty)
+          }
+          _ => unreachable!(),
+        });
+        return Ok(is_tty.unwrap());
+      }
+      Some(_) => return Ok(false),
+    };
+    Ok(atty::is(stream))
+  }
+
+  /// Receives a single datagram (for UdpSocket resource).
+  /// On success, returns the number of bytes read and the peer address.
+  pub fn poll_recv_from(
+    &mut self,
+    buf: &mut [u8],
+  ) -> Poll<(usize, SocketAddr), Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      None => Err(bad_rid_io_error()),
+      Some(repr) => match repr {
+        Repr::UdpSocket(ref mut s) => s.poll_recv_from(buf),
+        _ => Err(bad_rid_io_error()),
+      },
+    }
+  }
+
+  /// Sends a single datagram to `target` (for UdpSocket resource).
+  pub fn poll_send_to(
+    &mut self,
+    buf: &[u8],
+    target: &SocketAddr,
+  ) -> Poll<usize, Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      None => Err(bad_rid_io_error()),
+      Some(repr) => match repr {
+        Repr::UdpSocket(ref mut s) => s.poll_send_to(buf, target),
+        _ => Err(bad_rid_io_error()),
+      },
+    }
+  }
+
+  /// Returns the local address of a socket resource.
+  pub fn local_addr(&self) -> DenoResult<SocketAddr> {
+    let table = RESOURCE_TABLE.lock().unwrap();
+    match table.get(&self.rid) {
+      Some(Repr::TcpListener(ref s, _)) => s.local_addr(),
+      Some(Repr::TcpStream(ref s)) => s.local_addr(),
+      Some(Repr::UdpSocket(ref s)) => s.local_addr(),
+      Some(Repr::TlsStream(ref s)) => s.get_ref().0.local_addr(),
+      Some(Repr::ServerTlsStream(ref s)) => s.get_ref().0.local_addr(),
+      _ => return Err(bad_resource()),
+    }.map_err(DenoError::from)
+  }
+
+  /// Returns the remote address of a connected stream resource.
+  pub fn peer_addr(&self) -> DenoResult<SocketAddr> {
+    let table = RESOURCE_TABLE.lock().unwrap();
+    match table.get(&self.rid) {
+      Some(Repr::TcpStream(ref s)) => s.peer_addr(),
+      Some(Repr::TlsStream(ref s)) => s.get_ref().0.peer_addr(),
+      Some(Repr::ServerTlsStream(ref s)) => s.get_ref().0.peer_addr(),
+      _ => return Err(bad_resource()),
+    }.map_err(DenoError::from)
+  }
+
+  /// Reads incoming data (for TcpStream resource) without removing it from
+  /// the socket's receive queue, so the next read returns the same bytes.
+  pub fn poll_peek(&mut self, buf: &mut [u8]) -> Poll<usize, DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::TcpStream(ref mut s)) => {
+        s.poll_peek(buf).map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Enables or disables Nagle's algorithm (for TcpStream resource).
+  pub fn set_nodelay(&mut self, nodelay: bool) -> DenoResult<()> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::TcpStream(ref mut s)) => {
+        s.set_nodelay(nodelay).map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Sets SO_KEEPALIVE (for TcpStream resource). `None` disables keepalive.
+  pub fn set_keepalive(
+    &mut self,
+    keepalive: Option<Duration>,
+  ) -> DenoResult<()> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::TcpStream(ref mut s)) => {
+        s.set_keepalive(keepalive).map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Yields the next entry of a FsDir resource, or None once the directory
+  /// has been exhausted.
+  pub fn poll_read_dir(&mut self) -> Poll<Option<DirEntryInfo>, Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      None => Err(bad_rid_io_error()),
+      Some(Repr::FsDir(ref mut read_dir, ref mut pending)) => {
+        if pending.is_none() {
+          *pending = try_ready!(read_dir.poll());
+          if pending.is_none() {
+            return Ok(Async::Ready(None));
+          }
+        }
+        let metadata = try_ready!(pending.as_mut().unwrap().poll_metadata());
+        let entry = pending.take().unwrap();
+        Ok(Async::Ready(Some(DirEntryInfo {
+          name: entry.file_name(),
+          file_type: metadata.file_type(),
+          len: metadata.len(),
+        })))
+      }
+      _ => Err(bad_rid_io_error()),
+    }
+  }
+
+  /// Moves the cursor of a FsFile resource. Resolves to the new offset from
+  /// the start of the file.
+  pub fn poll_seek(&mut self, pos: SeekFrom) -> Poll<u64, DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      Some(Repr::FsFile(ref mut f)) => {
+        f.poll_seek(pos).map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Truncates or extends a FsFile resource to `size` bytes. Extending fills
+  /// the new space with zeros. The cursor is not moved.
+  pub fn set_len(&mut self, size: u64) -> Poll<(), DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      Some(Repr::FsFile(ref mut f)) => {
+        f.poll_set_len(size).map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Flushes the data and metadata of a FsFile resource to disk, like
+  /// fsync(2).
+  pub fn poll_sync_all(&mut self) -> Poll<(), DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      Some(Repr::FsFile(ref mut f)) => {
+        f.poll_sync_all().map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Like `poll_sync_all()`, but may skip flushing metadata that is not
+  /// needed to read the data back, like fdatasync(2).
+  pub fn poll_sync_data(&mut self) -> Poll<(), DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      Some(Repr::FsFile(ref mut f)) => {
+        f.poll_sync_data().map_err(DenoError::from)
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Queries the metadata of a FsFile resource, like fstat(2).
+  pub fn poll_metadata(&mut self) -> Poll<FileMetadata, DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      Some(Repr::FsFile(ref mut f)) => {
+        let metadata = try_ready!(f.poll_metadata());
+        Ok(Async::Ready(FileMetadata {
+          len: metadata.len(),
+          modified: metadata.modified().ok(),
+          accessed: metadata.accessed().ok(),
+          readonly: metadata.permissions().readonly(),
+        }))
+      }
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Reads from a FsFile resource at `offset` into `buf`, like pread(2). The
+  /// cursor is not moved on unix; on windows it ends up after the bytes read.
+  pub fn poll_read_at(
+    &mut self,
+    offset: u64,
+    buf: &mut [u8],
+  ) -> Poll<usize, DenoError> {
+    self.poll_positional(|f| file_read_at(f, offset, buf))
+  }
+
+  /// Writes `buf` to a FsFile resource at `offset`, like pwrite(2). The
+  /// cursor is not moved on unix; on windows it ends up after the bytes
+  /// written.
+  pub fn poll_write_at(
+    &mut self,
+    offset: u64,
+    buf: &[u8],
+  ) -> Poll<usize, DenoError> {
+    self.poll_positional(|f| file_write_at(f, offset, buf))
+  }
+
+  fn poll_positional<F>(&mut self, f: F) -> Poll<usize, DenoError>
+  where
+    F: FnOnce(&std::fs::File) -> std::io::Result<usize>,
+  {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get(&self.rid) {
+      Some(Repr::FsFile(_)) => {}
+      _ => return Err(bad_resource()),
+    }
+    fs_file_blocking(&mut table, &self.rid, f).map_err(DenoError::from)
+  }
+
+  /// Writes `bufs` in order, like writev(2). On unix, TcpStream and FsFile
+  /// resources write all of them with a single syscall. Other writable
+  /// resources write them one after the other with `poll_write()`, stopping
+  /// at the first one that can't be written completely.
+  pub fn poll_write_vectored(&mut self, bufs: &[&[u8]]) -> Poll<usize, Error> {
+    let r = {
+      let mut table = RESOURCE_TABLE.lock().unwrap();
+      let r = match table.get_mut(&self.rid) {
+        None => Some(Err(bad_rid_io_error())),
+        #[cfg(unix)]
+        Some(Repr::TcpStream(ref mut s)) => Some(tcp_write_vectored(s, bufs)),
+        #[cfg(unix)]
+        Some(Repr::FsFile(_)) => {
+          Some(fs_file_blocking(&mut table, &self.rid, |f| {
+            use std::os::unix::io::AsRawFd;
+            writev(f.as_raw_fd(), bufs)
+          }))
+        }
+        _ => None,
+      };
+      if let Some(Ok(Async::Ready(nwritten))) = r {
+        let entry = table.entry(&self.rid).unwrap();
+        entry.bytes_written.fetch_add(nwritten, Ordering::SeqCst);
+      }
+      r
+    };
+    match r {
+      Some(r) => r,
+      None => self.write_sequential(bufs),
+    }
+  }
+
+  /// Reads into `bufs` in order, like readv(2). On unix, TcpStream and FsFile
+  /// resources fill them with a single syscall. Other readable resources,
+  /// like HttpBody, fill them one after the other with `poll_read()`,
+  /// stopping at the first one that isn't filled completely.
+  pub fn poll_read_vectored(
+    &mut self,
+    bufs: &mut [&mut [u8]],
+  ) -> Poll<usize, Error> {
+    let r = {
+      let mut table = RESOURCE_TABLE.lock().unwrap();
+      let r = match table.get_mut(&self.rid) {
+        None => Some(Err(bad_rid_io_error())),
+        #[cfg(unix)]
+        Some(Repr::TcpStream(ref mut s)) => Some(tcp_read_vectored(s, bufs)),
+        #[cfg(unix)]
+        Some(Repr::FsFile(_)) => {
+          Some(fs_file_blocking(&mut table, &self.rid, |f| {
+            use std::os::unix::io::AsRawFd;
+            readv(f.as_raw_fd(), bufs)
+          }))
+        }
+        _ => None,
+      };
+      if let Some(Ok(Async::Ready(nread))) = r {
+        let entry = table.entry(&self.rid).unwrap();
+        entry.bytes_read.fetch_add(nread, Ordering::SeqCst);
+      }
+      r
+    };
+    match r {
+      Some(r) => r,
+      None => self.read_sequential(bufs),
+    }
+  }
+
+  fn read_sequential(&mut self, bufs: &mut [&mut [u8]]) -> Poll<usize, Error> {
+    let mut total = 0;
+    for buf in bufs.iter_mut().filter(|buf| !buf.is_empty()) {
+      match self.poll_read(buf) {
+        Ok(Async::Ready(n)) => {
+          total += n;
+          if n < buf.len() {
+            break;
+          }
+        }
+        // Report what was read so far; the rest is read by the next call.
+        Ok(Async::NotReady) | Err(_) if total > 0 => break,
+        r => return r,
+      }
+    }
+    Ok(Async::Ready(total))
+  }
+
+  fn write_sequential(&mut self, bufs: &[&[u8]]) -> Poll<usize, Error> {
+    let mut total = 0;
+    for buf in bufs.iter().filter(|buf| !buf.is_empty()) {
+      match self.poll_write(buf) {
+        Ok(Async::Ready(n)) => {
+          total += n;
+          if n < buf.len() {
+            break;
+          }
+        }
+        // Report what was written so far; the caller retries the rest.
+        Ok(Async::NotReady) | Err(_) if total > 0 => break,
+        r => return r,
+      }
+    }
+    Ok(Async::Ready(total))
+  }
+
+  /// Yields the next variable of an env iterator resource, or None once all
+  /// of them have been yielded.
+  pub fn poll_next_env(&mut self) -> Poll<Option<(String, String)>, DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    match table.get_mut(&self.rid) {
+      Some(Repr::EnvIter(ref mut iter)) => Ok(Async::Ready(iter.next())),
+      _ => Err(bad_resource()),
+    }
+  }
+
+  /// Yields the next line of a line reader resource, without the line
+  /// ending, or None at EOF.
+  pub fn poll_next_line(&mut self) -> Poll<Option<String>, Error> {
+    let lines = match RESOURCE_TABLE.lock().unwrap().get(&self.rid) {
+      Some(Repr::LineReader(_, ref lines)) => lines.clone(),
+      _ => return Err(bad_rid_io_error()),
+    };
+    let mut lines = lines.lock().unwrap();
+    lines.poll()
+  }
+
+  /// Copies bytes starting at `offset` of a shared buffer resource into
+  /// `buf`. Returns the number of bytes copied, which is less than
+  /// `buf.len()` if the end of the shared buffer is reached.
+  pub fn read_shared_buf(
+    &self,
+    offset: usize,
+    buf: &mut [u8],
+  ) -> DenoResult<usize> {
+    let shared = get_shared_buf(self.rid)?;
+    let shared = shared.lock().unwrap();
+    if offset > shared.len() {
+      return Err(shared_buf_out_of_range());
+    }
+    let n = std::cmp::min(buf.len(), shared.len() - offset);
+    buf[..n].copy_from_slice(&shared[offset..offset + n]);
+    Ok(n)
+  }
+
+  /// Copies `data` into a shared buffer resource at `offset`. The shared
+  /// buffer does not grow, so the whole of `data` must fit.
+  pub fn write_shared_buf(&self, offset: usize, data: &[u8]) -> DenoResult<()> {
+    let shared = get_shared_buf(self.rid)?;
+    let mut shared = shared.lock().unwrap();
+    match offset.checked_add(data.len()) {
+      Some(end) if end <= shared.len() => {
+        shared[offset..end].copy_from_slice(data);
+        Ok(())
+      }
+      _ => Err(shared_buf_out_of_range()),
+    }
+  }
+
+  pub fn shutdown(&mut self, how: Shutdown) -> Result<(), DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      None => Err(bad_resource()),
+      Some(repr) => match repr {
+        Repr::TcpStream(ref mut f) => {
+          TcpStream::shutdown(f, how).map_err(DenoError::from)
+        }
+        Repr::TlsStream(ref mut f) => {
+          let (tcp_stream, session) = f.get_mut();
+          tls_shutdown(tcp_stream, session, how)
+        }
+        Repr::ServerTlsStream(ref mut f) => {
+          let (tcp_stream, session) = f.get_mut();
+          tls_shutdown(tcp_stream, session, how)
+        }
+        _ => Err(bad_resource()),
+      },
+    }
+  }
+
+  /// Shuts down the write half of a socket, so that the peer sees EOF,
+  /// while data can still be read from it.
+  pub fn shutdown_write(&mut self) -> Result<(), DenoError> {
+    self.shutdown(Shutdown::Write)
+  }
+
+  /// Shuts down the read half of a socket. Data can still be written to it.
+  pub fn shutdown_read(&mut self) -> Result<(), DenoError> {
+    self.shutdown(Shutdown::Read)
+  }
+}
+
+#[test]
+fn test_shutdown_write() {
+  let (local, peer) = tcp_loopback_pair();
+  let mut resource = add_tcp_stream(local).unwrap();
+  resource.shutdown_write().unwrap();
+
+  // The peer sees EOF, but can still send data back.
+  let (peer, _, n) = tokio::io::read(peer, [0u8; 8]).wait().unwrap();
+  assert_eq!(n, 0);
+  tokio::io::write_all(peer, b"hello").wait().unwrap();
+  let (resource, buf) =
+    tokio::io::read_exact(resource, [0u8; 5]).wait().unwrap();
+  assert_eq!(&buf, b"hello");
+
+  let mut file =
+    add_fs_file(tokio::fs::File::from_std(tempfile::tempfile().unwrap()))
+      .unwrap();
+  let err = file.shutdown_write().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let err = file.shutdown_read().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  file.close();
+  resource.close();
+}
+
+// Sends the TLS close_notify alert (unless only the read half is being shut
+// down) before shutting down the underlying TcpStream.
+fn tls_shutdown<S: Session>(
+  tcp_stream: &mut TcpStream,
+  session: &mut S,
+  how: Shutdown,
+) -> DenoResult<()> {
+  if how != Shutdown::Read {
+    session.send_close_notify();
+    while session.wants_write() {
+      session.write_tls(tcp_stream)?;
+    }
+  }
+  TcpStream::shutdown(tcp_stream, how).map_err(DenoError::from)
+}
+
+// Read and Write are blocking: they poll the AsyncRead/AsyncWrite impls below
+// from a task of their own and park the calling thread until the resource is
+// ready, so they also work where no task is running. Blocking stalls the
+// event loop, so futures should use AsyncRead/AsyncWrite instead.
+fn wait_for<T, F>(f: F) -> std::io::Result<T>
+where
+  F: FnMut() -> Poll<T, Error>,
+{
+  futures::executor::spawn(futures::future::poll_fn(f)).wait_future()
+}
+
+impl Read for Resource {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    wait_for(|| self.poll_read(buf))
+  }
+}
+
+impl AsyncRead for Resource {
+  fn poll_read(&mut self, buf: &mut [u8]) -> Poll<usize, Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    poll_read_in(&mut table, self.rid, buf)
+  }
+}
+
+// Reads from `rid` in `table` and counts the bytes read. This backs
+// AsyncRead for Resource, and lets tests use a table of their own.
+fn poll_read_in(
+  table: &mut ResourceTable,
+  rid: ResourceId,
+  buf: &mut [u8],
+) -> Poll<usize, Error> {
+  let maybe_repr = table.get_mut(&rid);
+  let r = match maybe_repr {
+    None => Err(bad_rid_io_error()),
+    Some(repr) => match repr {
+      Repr::FsFile(ref mut f) => f.poll_read(buf),
+      Repr::Stdin(ref mut f) => f.poll_read(buf),
+      Repr::TcpStream(ref mut f) => f.poll_read(buf),
+      Repr::TlsStream(ref mut f) => f.poll_read(buf),
+      Repr::ServerTlsStream(ref mut f) => f.poll_read(buf),
+      #[cfg(unix)]
+      Repr::UnixStream(ref mut f) => f.poll_read(buf),
+      Repr::HttpBody(ref mut f) => f.poll_read(buf),
+      Repr::MemPipeReader(ref mut f) => f.poll_read(buf),
+      Repr::ChildStdout(ref mut f) => f.poll_read(buf),
+      Repr::ChildStderr(ref mut f) => f.poll_read(buf),
+      _ => panic!("Cannot read"),
+    },
+  };
+  if let Ok(Async::Ready(nread)) = r {
+    let entry = table.entry(&rid).unwrap();
+    entry.bytes_read.fetch_add(nread, Ordering::SeqCst);
+  }
+  r
+}
+
+impl Write for Resource {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    wait_for(|| self.poll_write(buf))
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    wait_for(|| self.poll_flush())
+  }
+}
+
+// Wraps a resource for adapters that go through Read, like BufReader, from
+// within a task. Unlike Resource itself it doesn't block, but maps NotReady
+// to WouldBlock as tokio expects, so it must only be used from within a task.
+struct PolledResource(Resource);
+
+fn poll_to_io<T>(p: Poll<T, Error>) -> std::io::Result<T> {
+  match p? {
+    Async::Ready(t) => Ok(t),
+    Async::NotReady => Err(std::io::ErrorKind::WouldBlock.into()),
+  }
+}
+
+impl Read for PolledResource {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    poll_to_io(self.0.poll_read(buf))
+  }
+}
+
+impl AsyncRead for PolledResource {}
+
+// Like poll_read_in(), but writes to `rid` and counts the bytes written.
+fn poll_write_in(
+  table: &mut ResourceTable,
+  rid: ResourceId,
+  buf: &[u8],
+) -> Poll<usize, Error> {
+  let maybe_repr = table.get_mut(&rid);
+  let r = match maybe_repr {
+    None => Err(bad_rid_io_error()),
+    Some(repr) => match repr {
+      Repr::FsFile(ref mut f) => f.poll_write(buf),
+      Repr::Stdout(ref mut f) => f.poll_write(buf),
+      Repr::Stderr(ref mut f) => f.poll_write(buf),
+      Repr::TcpStream(ref mut f) => f.poll_write(buf),
+      Repr::TlsStream(ref mut f) => f.poll_write(buf),
+      Repr::ServerTlsStream(ref mut f) => f.poll_write(buf),
+      #[cfg(unix)]
+      Repr::UnixStream(ref mut f) => f.poll_write(buf),
+      Repr::ChildStdin(ref mut f) => f.poll_write(buf),
+      Repr::MemPipeWriter(ref mut f) => f.poll_write(buf),
+      _ => panic!("Cannot write"),
+    },
+  };
+  if let Ok(Async::Ready(nwritten)) = r {
+    let entry = table.entry(&rid).unwrap();
+    entry.bytes_written.fetch_add(nwritten, Ordering::SeqCst);
+  }
+  r
+}
+
+impl AsyncWrite for Resource {
+  fn poll_write(&mut self, buf: &[u8]) -> Poll<usize, Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    poll_write_in(&mut table, self.rid, buf)
+  }
+
+  fn poll_flush(&mut self) -> Poll<(), Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      None => Err(bad_rid_io_error()),
+      Some(repr) => match repr {
+        Repr::FsFile(ref mut f) => f.poll_flush(),
+        Repr::Stdout(ref mut f) => f.poll_flush(),
+        Repr::Stderr(ref mut f) => f.poll_flush(),
+        Repr::TcpStream(ref mut f) => f.poll_flush(),
+        Repr::TlsStream(ref mut f) => f.poll_flush(),
+        Repr::ServerTlsStream(ref mut f) => f.poll_flush(),
+        #[cfg(unix)]
+        Repr::UnixStream(ref mut f) => f.poll_flush(),
+        Repr::ChildStdin(ref mut f) => f.poll_flush(),
+        Repr::MemPipeWriter(ref mut f) => f.poll_flush(),
+        // Nothing is buffered for the other resources.
+        _ => Ok(Async::Ready(())),
+      },
+    }
+  }
+
+  fn shutdown(&mut self) -> futures::Poll<(), std::io::Error> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      None => Err(bad_rid_io_error()),
+      // Use UFCS for disambiguation: TcpStream also has an inherent
+      // shutdown(Shutdown) method.
+      Some(repr) => match repr {
+        Repr::FsFile(ref mut f) => AsyncWrite::shutdown(f),
+        Repr::Stdout(ref mut f) => AsyncWrite::shutdown(f),
+        Repr::Stderr(ref mut f) => AsyncWrite::shutdown(f),
+        Repr::TcpStream(ref mut f) => AsyncWrite::shutdown(f),
+        Repr::TlsStream(ref mut f) => AsyncWrite::shutdown(f),
+        Repr::ServerTlsStream(ref mut f) => AsyncWrite::shutdown(f),
+        #[cfg(unix)]
+        Repr::UnixStream(ref mut f) => AsyncWrite::shutdown(f),
+        Repr::ChildStdin(ref mut f) => AsyncWrite::shutdown(f),
+        Repr::MemPipeWriter(ref mut f) => AsyncWrite::shutdown(f),
+        _ => Ok(futures::Async::Ready(())),
+      },
+    }
+  }
+}
+
+#[test]
+fn test_fs_file_shutdown() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("shutdown.txt");
+  let std_file = std::fs::File::create(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  tokio_util::init(|| {
+    let (resource, _) =
+      tokio_util::block_on(tokio::io::write_all(resource, b"hello")).unwrap();
+    let resource = tokio_util::block_on(tokio::io::shutdown(resource)).unwrap();
+    resource.close();
+  });
+  assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+}
+
+#[test]
+fn test_fs_file_poll_seek() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("seek.txt");
+  let std_file = std::fs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create(true)
+    .open(&path)
+    .unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  tokio_util::init(|| {
+    let (resource, _) =
+      tokio_util::block_on(tokio::io::write_all(resource, b"hello")).unwrap();
+    let mut seek_resource = resource.clone();
+    let pos = tokio_util::block_on(futures::future::poll_fn(move || {
+      seek_resource.poll_seek(SeekFrom::Start(0))
+    })).unwrap();
+    assert_eq!(pos, 0);
+    let (resource, buf) =
+      tokio_util::block_on(tokio::io::read_exact(resource, [0u8; 1])).unwrap();
+    assert_eq!(&buf, b"h");
+    resource.close();
+  });
+}
+
+#[test]
+fn test_bad_rid_does_not_panic() {
+  let rid = 9999;
+  assert!(lookup(rid).is_none());
+  let mut resource = Resource { rid };
+  let mut buf = [0u8; 1];
+  let err = resource.poll_read(&mut buf).unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+  let err = resource.poll_write(&buf).unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+  let err = resource.shutdown(Shutdown::Both).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+#[test]
+fn test_fs_file_set_len() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("set_len.txt");
+  let std_file = std::fs::File::create(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  tokio_util::init(|| {
+    let (resource, _) =
+      tokio_util::block_on(tokio::io::write_all(resource, b"0123456789"))
+        .unwrap();
+    let mut r = resource.clone();
+    tokio_util::block_on(futures::future::poll_fn(move || r.set_len(4)))
+      .unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 4);
+    let mut r = resource.clone();
+    tokio_util::block_on(futures::future::poll_fn(move || r.set_len(6)))
+      .unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"0123\0\0");
+    resource.close();
+  });
+}
+
+#[test]
+fn test_fs_file_sync() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("sync.txt");
+  let std_file = std::fs::File::create(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  tokio_util::init(|| {
+    let (resource, _) =
+      tokio_util::block_on(tokio::io::write_all(resource, b"hello")).unwrap();
+    let mut r = resource.clone();
+    tokio_util::block_on(futures::future::poll_fn(move || r.poll_sync_data()))
+      .unwrap();
+    let mut r = resource.clone();
+    tokio_util::block_on(futures::future::poll_fn(move || r.poll_sync_all()))
+      .unwrap();
+    resource.close();
+  });
+  assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+}
+
+#[test]
+fn test_fs_file_metadata() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("metadata.txt");
+  let std_file = std::fs::File::create(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  tokio_util::init(|| {
+    let (resource, _) =
+      tokio_util::block_on(tokio::io::write_all(resource, b"1234567")).unwrap();
+    let mut r = resource.clone();
+    let metadata =
+      tokio_util::block_on(futures::future::poll_fn(move || r.poll_metadata()))
+        .unwrap();
+    assert_eq!(metadata.len, 7);
+    assert!(metadata.modified.is_some());
+    assert!(!metadata.readonly);
+    resource.close();
+  });
+}
+
+#[cfg(unix)]
+fn file_is_tty(f: &std::fs::File) -> bool {
+  use std::os::unix::io::AsRawFd;
+  unsafe { libc::isatty(f.as_raw_fd()) == 1 }
+}
+
+#[cfg(windows)]
+fn file_is_tty(_f: &std::fs::File) -> bool {
+  false
+}
+
+#[test]
+fn test_is_tty() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let std_file = std::fs::File::create(dir.path().join("tty.txt")).unwrap();
+  let file = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  assert!(!file.is_tty().unwrap());
+  // Still usable afterwards.
+  assert_eq!(resource_kind(file.rid), Some(ResourceKind::FsFile));
+  file.close();
+
+  let (reader, writer) = new_pipe().unwrap();
+  assert!(!reader.is_tty().unwrap());
+  assert!(!writer.is_tty().unwrap());
+  reader.close();
+  writer.close();
+
+  // Whatever the test runner redirected stdio to.
+  assert_eq!(
+    Resource { rid: 0 }.is_tty().unwrap(),
+    atty::is(atty::Stream::Stdin)
+  );
+  assert_eq!(
+    Resource { rid: 1 }.is_tty().unwrap(),
+    atty::is(atty::Stream::Stdout)
+  );
+  assert_eq!(
+    Resource { rid: 2 }.is_tty().unwrap(),
+    atty::is(atty::Stream::Stderr)
+  );
+  let err = Resource { rid: 9999 }.is_tty().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+#[cfg(unix)]
+fn file_read_at(
+  f: &std::fs::File,
+  offset: u64,
+  buf: &mut [u8],
+) -> std::io::Result<usize> {
+  std::os::unix::fs::FileExt::read_at(f, buf, offset)
+}
+
+#[cfg(windows)]
+fn file_read_at(
+  f: &std::fs::File,
+  offset: u64,
+  buf: &mut [u8],
+) -> std::io::Result<usize> {
+  std::os::windows::fs::FileExt::seek_read(f, buf, offset)
+}
+
+#[cfg(unix)]
+fn file_write_at(
+  f: &std::fs::File,
+  offset: u64,
+  buf: &[u8],
+) -> std::io::Result<usize> {
+  std::os::unix::fs::FileExt::write_at(f, buf, offset)
+}
+
+#[cfg(windows)]
+fn file_write_at(
+  f: &std::fs::File,
+  offset: u64,
+  buf: &[u8],
+) -> std::io::Result<usize> {
+  std::os::windows::fs::FileExt::seek_write(f, buf, offset)
+}
+
+#[test]
+fn test_fs_file_read_write_at() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("read_write_at.txt");
+  let std_file = std::fs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create(true)
+    .open(&path)
+    .unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  tokio_util::init(|| {
+    let mut r = resource.clone();
+    let nwritten = tokio_util::block_on(futures::future::poll_fn(move || {
+      r.poll_write_at(100, b"hello")
+    })).unwrap();
+    assert_eq!(nwritten, 5);
+    let mut r = resource.clone();
+    let data = tokio_util::block_on(futures::future::poll_fn(
+      move || -> Poll<Vec<u8>, DenoError> {
+        let mut buf = [0u8; 16];
+        let n = try_ready!(r.poll_read_at(100, &mut buf));
+        Ok(Async::Ready(buf[..n].to_vec()))
+      },
+    ))
+    .unwrap();
+    assert_eq!(data, b"hello");
+    resource.close();
+  });
+  let contents = std::fs::read(&path).unwrap();
+  assert_eq!(contents.len(), 105);
+  assert_eq!(&contents[100..], b"hello");
+}
+
+#[test]
+fn test_fs_file_ops_bad_resource() {
+  let mut resource = Resource { rid: 0 };
+  let mut buf = [0u8; 4];
+  let err = resource.poll_read_at(0, &mut buf).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let err = resource.poll_write_at(0, &buf).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let err = resource.poll_metadata().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let err = resource.set_len(0).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let err = resource.poll_sync_all().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let err = resource.poll_sync_data().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+#[test]
+fn test_fs_file_stats() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("stats.txt");
+  let std_file = std::fs::File::create(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  assert_eq!(resource.stats().unwrap(), ResourceStats::default());
+  tokio_util::init(|| {
+    let (resource, _) =
+      tokio_util::block_on(tokio::io::write_all(resource, b"hello")).unwrap();
+    let stats = resource.stats().unwrap();
+    assert_eq!(stats.bytes_written, 5);
+    assert_eq!(stats.bytes_read, 0);
+    let entries = table_entries_with_stats();
+    let entry = entries.iter().find(|(rid, _, _)| *rid == resource.rid);
+    assert_eq!(entry, Some(&(resource.rid, ResourceKind::FsFile, stats)));
+    resource.close();
+  });
+}
+
+#[test]
+fn test_sync_io() {
+  // None of this runs within a task.
+  let (mut reader, mut writer) = new_pipe().unwrap();
+  assert_eq!(writer.write(b"hello").unwrap(), 5);
+  writer.flush().unwrap();
+  let mut buf = [0u8; 8];
+  assert_eq!(reader.read(&mut buf).unwrap(), 5);
+  assert_eq!(&buf[..5], b"hello");
+
+  // A read waits for the writer rather than failing.
+  let mut w = writer.clone();
+  let t = std::thread::spawn(move || {
+    std::thread::sleep(Duration::from_millis(50));
+    w.write_all(b"world").unwrap();
+  });
+  assert_eq!(reader.read(&mut buf).unwrap(), 5);
+  assert_eq!(&buf[..5], b"world");
+  t.join().unwrap();
+
+  // std::io::copy works too, up to EOF.
+  writer.write_all(b"copied").unwrap();
+  writer.close();
+  let mut copied = Vec::new();
+  assert_eq!(std::io::copy(&mut reader, &mut copied).unwrap(), 6);
+  assert_eq!(copied, b"copied");
+  reader.close();
+
+  // Errors are reported rather than panicking; the fs file can only be
+  // polled on the tokio threadpool.
+  let file = tempfile::tempfile().unwrap();
+  let mut file = add_fs_file(tokio::fs::File::from_std(file)).unwrap();
+  assert!(file.read(&mut buf).is_err());
+  file.close();
+  // A bad rid is an error rather than a panic.
+  let mut resource = Resource { rid: 9999 };
+  let err = resource.read(&mut [0u8; 1]).unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+  let err = resource.write(b"x").unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_poll_seek_bad_resource() {
+  // stdin cannot be seeked.
+  let mut resource = Resource { rid: 0 };
+  let r = resource.poll_seek(SeekFrom::Start(0));
+  assert_eq!(r.unwrap_err().kind(), errors::ErrorKind::BadResource);
+}
+
+// close(2) is done by dropping the value. Therefore we just need to remove
+// the resource from the RESOURCE_TABLE.
+// If the rid was dup'd, the resource stays open until every rid referring to
+// it has been closed.
+pub fn close(rid: ResourceId) -> DenoResult<()> {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  match table.remove(&rid) {
+    None => Err(bad_resource()),
+    Some(maybe_repr) => {
+      if let Some(ref repr) = maybe_repr {
+        notify_closed(repr);
+        // A line reader owns the resource it reads from.
+        if let Repr::LineReader(inner_rid, _) = repr {
+          if let Some(Some(inner)) = table.remove(inner_rid) {
+            notify_closed(&inner);
+          }
+        }
+      }
+      Ok(())
+    }
+  }
+}
+
+/// A `Resource` that is closed when dropped, to avoid leaking a freshly
+/// added resource on an early return. `Resource` itself is a plain handle:
+/// any number of them can refer to a rid, and dropping one never closes it.
+/// Call `release()` to keep the rid open and get the plain handle back.
+///
+/// ```ignore
+/// let file = OwnedResource::new(add_fs_file(f)?);
+/// let status = something_fallible()?; // Closes the file on error.
+/// Ok((file.release(), status))
+/// ```
+pub struct OwnedResource(Option<Resource>);
+
+impl OwnedResource {
+  pub fn new(resource: Resource) -> Self {
+    OwnedResource(Some(resource))
+  }
+
+  /// Gives up ownership: the rid stays open after this is dropped.
+  pub fn release(mut self) -> Resource {
+    self.0.take().unwrap()
+  }
+}
+
+impl std::ops::Deref for OwnedResource {
+  type Target = Resource;
+
+  fn deref(&self) -> &Resource {
+    self.0.as_ref().unwrap()
+  }
+}
+
+impl std::ops::DerefMut for OwnedResource {
+  fn deref_mut(&mut self) -> &mut Resource {
+    self.0.as_mut().unwrap()
+  }
+}
+
+impl Drop for OwnedResource {
+  fn drop(&mut self) {
+    if let Some(resource) = self.0.take() {
+      // It may have been closed through another handle already; fine.
+      let _ = close(resource.rid);
+    }
+  }
+}
+
+#[test]
+fn test_owned_resource() {
+  let (reader, writer) = new_pipe().unwrap();
+  let reader_rid = reader.rid;
+  let writer_rid = writer.rid;
+  let owned = OwnedResource::new(reader);
+  assert_eq!(owned.rid, reader_rid);
+  drop(owned);
+  assert!(!table_entries().iter().any(|(rid, _)| *rid == reader_rid));
+
+  let writer = OwnedResource::new(writer).release();
+  assert!(table_entries().iter().any(|(rid, _)| *rid == writer_rid));
+  writer.close();
+
+  // Dropping after the rid was closed elsewhere is harmless.
+  let (reader, writer) = new_pipe().unwrap();
+  let owned = OwnedResource::new(reader);
+  close(owned.rid).unwrap();
+  drop(owned);
+  writer.close();
+}
+
+/// Like `close()`, but first flushes writable resources so that nothing
+/// buffered is lost. The resource is closed even if flushing fails; the
+/// flush error is returned in that case.
+pub fn close_flushing(
+  rid: ResourceId,
+) -> impl Future<Item = (), Error = DenoError> {
+  let writable = match resource_kind(rid) {
+    None => return Either::A(futures::future::err(bad_resource())),
+    Some(kind) => kind.capabilities().writable,
+  };
+  if !writable {
+    return Either::A(futures::future::result(close(rid)));
+  }
+  let flush = tokio::io::flush(Resource { rid }).then(move |r| {
+    let closed = close(rid);
+    r.map_err(DenoError::from)?;
+    closed
+  });
+  Either::B(flush)
+}
+
+#[test]
+fn test_close_flushing() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("flushed.txt");
+  let std_file = std::fs::File::create(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  let rid = resource.rid;
+  tokio_util::init(|| {
+    tokio_util::block_on(tokio::io::write_all(resource, b"flushed")).unwrap();
+    tokio_util::block_on(close_flushing(rid)).unwrap();
+  });
+  assert!(lookup(rid).is_none());
+  assert_eq!(std::fs::read(&path).unwrap(), b"flushed");
+
+  let (reader, writer) = new_pipe().unwrap();
+  close_flushing(reader.rid).wait().unwrap();
+  assert!(lookup(reader.rid).is_none());
+  writer.close();
+
+  let err = close_flushing(rid).wait().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+// Wakes up the task waiting on a resource that was just closed, so that it
+// errors out instead of staying pending forever.
+fn notify_closed(repr: &Repr) {
+  match repr {
+    // If TcpListener, we must kill all pending accepts!
+    Repr::TcpListener(_, Some(t)) => t.notify(),
+    // Closing a timer cancels it.
+    Repr::Timer(_, Some(t)) => t.notify(),
+    Repr::Interval(_, Some(t)) => t.notify(),
+    // A DNS query waiting for room on the threadpool fails once closed.
+    Repr::DnsQuery(_, _, Some(t)) => t.notify(),
+    _ => {}
+  }
+}
+
+// Shuts down both halves of a socket, so that the peer sees an orderly close
+// even if some other handle keeps the socket itself open. Errors are ignored:
+// the resource is going away regardless.
+fn shutdown_socket(repr: &mut Repr) {
+  let _ = match repr {
+    Repr::TcpStream(ref mut s) => {
+      TcpStream::shutdown(s, Shutdown::Both).map_err(DenoError::from)
+    }
+    Repr::TlsStream(ref mut s) => {
+      let (tcp_stream, session) = s.get_mut();
+      tls_shutdown(tcp_stream, session, Shutdown::Both)
+    }
+    Repr::ServerTlsStream(ref mut s) => {
+      let (tcp_stream, session) = s.get_mut();
+      tls_shutdown(tcp_stream, session, Shutdown::Both)
+    }
+    #[cfg(unix)]
+    Repr::UnixStream(ref mut s) => {
+      s.shutdown(Shutdown::Both).map_err(DenoError::from)
+    }
+    _ => Ok(()),
+  };
+}
+
+/// Closes every resource except stdio, e.g. when the process is exiting.
+/// Sockets are shut down first. Calling this again closes nothing new.
+pub fn close_all_except_stdio() {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  table.close_all_except_stdio();
+}
+
+#[test]
+fn test_close_all_except_stdio() {
+  let mut table = ResourceTable::new();
+  table.insert(0, Repr::Stdin(tokio::io::stdin()));
+  table.insert(1, Repr::Stderr(tokio::io::stderr()));
+  table.insert(2, Repr::Stderr(tokio::io::stderr()));
+  let file = tempfile::tempfile().unwrap();
+  let file_rid =
+    insert(&mut table, Repr::FsFile(tokio::fs::File::from_std(file))).unwrap();
+  table.dup(file_rid, new_rid().unwrap()).unwrap();
+  let (client, server) = tcp_loopback_pair();
+  insert(&mut table, Repr::TcpStream(client)).unwrap();
+  assert_eq!(table.len(), 3);
+
+  table.close_all_except_stdio();
+  let mut rids: Vec<ResourceId> = table.iter().map(|(rid, _)| *rid).collect();
+  rids.sort();
+  assert_eq!(rids, vec![0, 1, 2]);
+  // The peer of the closed socket sees EOF.
+  let (_, _, n) = tokio::io::read(server, [0u8; 8]).wait().unwrap();
+  assert_eq!(n, 0);
+
+  table.close_all_except_stdio();
+  assert_eq!(table.iter().count(), 3);
+}
+
+#[test]
+fn test_isolated_table() {
+  let (mut table, mut stdio) = ResourceTable::new_isolated();
+  let n = futures::future::poll_fn(|| match table.get_mut(&1) {
+    Some(Repr::MemPipeWriter(ref mut w)) => w.poll_write(b"hello"),
+    _ => panic!("stdout should be a pipe"),
+  }).wait()
+  .unwrap();
+  assert_eq!(n, 5);
+  let mut buf = [0u8; 5];
+  let n = futures::future::poll_fn(|| stdio.stdout.poll_read(&mut buf))
+    .wait()
+    .unwrap();
+  assert_eq!(&buf[..n], b"hello");
+
+  futures::future::poll_fn(|| stdio.stdin.poll_write(b"in"))
+    .wait()
+    .unwrap();
+  let mut buf = [0u8; 2];
+  let n = futures::future::poll_fn(|| match table.get_mut(&0) {
+    Some(Repr::MemPipeReader(ref mut r)) => r.poll_read(&mut buf),
+    _ => panic!("stdin should be a pipe"),
+  }).wait()
+  .unwrap();
+  assert_eq!(&buf[..n], b"in");
+
+  // Closing the table's end of stderr is seen as EOF.
+  table.remove(&2);
+  let n = futures::future::poll_fn(|| stdio.stderr.poll_read(&mut buf))
+    .wait()
+    .unwrap();
+  assert_eq!(n, 0);
+  // The global table is untouched.
+  assert_eq!(resource_kind(1), Some(ResourceKind::Stdout));
+}
+
+/// One of the stdio rids 0-2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StdioStream {
+  Stdin,
+  Stdout,
+  Stderr,
+}
+
+impl StdioStream {
+  pub fn rid(self) -> ResourceId {
+    match self {
+      StdioStream::Stdin => 0,
+      StdioStream::Stdout => 1,
+      StdioStream::Stderr => 2,
+    }
+  }
+}
+
+/// Redirects a stdio rid to the resource `rid`, E.G. to send everything
+/// written to rid 1 to a file. The two are swapped: afterwards `rid` holds
+/// what the stdio rid held before, so calling this again with the same
+/// arguments undoes the redirection. Stdin can only be redirected to a
+/// readable resource, stdout and stderr only to writable ones.
+pub fn replace_stdio(which: StdioStream, rid: ResourceId) -> DenoResult<()> {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  let caps = match table.get(&rid) {
+    Some(repr) => ResourceKind::from(repr).capabilities(),
+    None => return Err(bad_resource()),
+  };
+  let usable = match which {
+    StdioStream::Stdin => caps.readable,
+    StdioStream::Stdout | StdioStream::Stderr => caps.writable,
+  };
+  if !usable {
+    return Err(errors::new(
+      errors::ErrorKind::InvalidInput,
+      String::from("resource cannot be used for this stdio stream"),
+    ));
+  }
+  table.swap_repr(&which.rid(), &rid).ok_or_else(bad_resource)
+}
+
+#[test]
+fn test_swap_stdio_repr() {
+  let mut table = ResourceTable::new();
+  table.insert(0, Repr::Stdin(tokio::io::stdin()));
+  table.insert(1, Repr::Stderr(tokio::io::stderr()));
+  table.insert(2, Repr::Stderr(tokio::io::stderr()));
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("stdout.txt");
+  let file = std::fs::File::create(&path).unwrap();
+  let file_rid =
+    insert(&mut table, Repr::FsFile(tokio::fs::File::from_std(file))).unwrap();
+
+  table
+    .swap_repr(&StdioStream::Stdout.rid(), &file_rid)
+    .unwrap();
+  assert_eq!(
+    table.get(&1).map(ResourceKind::from),
+    Some(ResourceKind::FsFile)
+  );
+  assert_eq!(
+    table.get(&file_rid).map(ResourceKind::from),
+    Some(ResourceKind::Stderr)
+  );
+  // Writes to rid 1 now end up in the file.
+  tokio_util::init(|| {
+    let write = futures::future::poll_fn(move || match table.get_mut(&1) {
+      Some(Repr::FsFile(ref mut f)) => f.poll_write(b"hello"),
+      _ => unreachable!(),
+    });
+    assert_eq!(tokio_util::block_on(write).unwrap(), 5);
+  });
+  assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+}
+
+#[test]
+fn test_replace_stdio_errors() {
+  let err = replace_stdio(StdioStream::Stdout, 9999).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let (reader, writer) = new_pipe().unwrap();
+  let err = replace_stdio(StdioStream::Stdout, reader.rid).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::InvalidInput);
+  let err = replace_stdio(StdioStream::Stdin, writer.rid).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::InvalidInput);
+  reader.close();
+  writer.close();
+}
+
+/// Returns a new rid that refers to the same underlying resource as `rid`,
+/// like dup(2). The two rids share all state: in particular, reads, writes
+/// and seeks on a dup'd file all move the same cursor.
+pub fn dup(rid: ResourceId) -> DenoResult<ResourceId> {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  if !table.contains_key(&rid) {
+    return Err(bad_resource());
+  }
+  table.check_max_resources()?;
+  let new_rid = new_rid()?;
+  if table.contains_key(&new_rid) {
+    return Err(errors::new(
+      errors::ErrorKind::AlreadyExists,
+      String::from("There is already a resource with that rid"),
+    ));
+  }
+  table.dup(rid, new_rid).unwrap();
+  Ok(new_rid)
+}
+
+#[test]
+fn test_dup() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("dup.txt");
+  std::fs::write(&path, b"hello").unwrap();
+  let std_file = std::fs::File::open(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  let dup_rid = dup(resource.rid).unwrap();
+  assert_ne!(dup_rid, resource.rid);
+  resource.close();
+  assert!(lookup(resource.rid).is_none());
+
+  let dup_resource = lookup(dup_rid).unwrap();
+  tokio_util::init(|| {
+    let (dup_resource, buf) =
+      tokio_util::block_on(tokio::io::read_exact(dup_resource, [0u8; 5]))
+        .unwrap();
+    assert_eq!(&buf, b"hello");
+    dup_resource.close();
+  });
+  assert!(lookup(dup_rid).is_none());
+}
+
+#[test]
+fn test_dup_bad_resource() {
+  let err = dup(9999).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+// Reads `resource` until EOF by calling poll_read() in a loop, and resolves
+// to the resource and everything that was read.
+#[cfg(test)]
+fn poll_read_to_end(
+  mut resource: Resource,
+) -> impl Future<Item = (Resource, Vec<u8>), Error = Error> {
+  let mut buf = Vec::new();
+  let mut chunk = [0u8; 1024];
+  futures::future::poll_fn(move || loop {
+    let nread = try_ready!(resource.poll_read(&mut chunk));
+    if nread == 0 {
+      let buf = std::mem::replace(&mut buf, Vec::new());
+      return Ok(Async::Ready((resource.clone(), buf)));
+    }
+    buf.extend_from_slice(&chunk[..nread]);
+  })
+}
+
+// Returns both ends of a connected loopback TCP connection.
+#[cfg(test)]
+fn tcp_loopback_pair() -> (TcpStream, TcpStream) {
+  let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+  let client =
+    std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+  let (server, _) = listener.accept().unwrap();
+  let handle = tokio::reactor::Handle::default();
+  (
+    TcpStream::from_std(client, &handle).unwrap(),
+    TcpStream::from_std(server, &handle).unwrap(),
+  )
+}
+
+#[test]
+fn test_socket_addrs() {
+  let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+  let listener = tokio::net::TcpListener::bind(&addr).unwrap();
+  let listener_resource = add_tcp_listener(listener).unwrap();
+  let listener_addr = listener_resource.local_addr().unwrap();
+  assert_ne!(listener_addr.port(), 0);
+  // A listener is not connected to a peer.
+  let err = listener_resource.peer_addr().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+
+  let std_stream = std::net::TcpStream::connect(&listener_addr).unwrap();
+  let stream =
+    TcpStream::from_std(std_stream, &tokio::reactor::Handle::default())
+      .unwrap();
+  let stream_resource = add_tcp_stream(stream).unwrap();
+  assert_eq!(stream_resource.peer_addr().unwrap(), listener_addr);
+  assert_ne!(stream_resource.local_addr().unwrap().port(), 0);
+
+  // stdin is not a socket.
+  let err = Resource { rid: 0 }.local_addr().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+
+  stream_resource.close();
+  listener_resource.close();
+}
+
+#[test]
+fn test_set_nodelay_and_keepalive() {
+  let (client, _server) = tcp_loopback_pair();
+  let mut resource = add_tcp_stream(client).unwrap();
+  assert!(resource.set_nodelay(true).is_ok());
+  assert!(resource
+    .set_keepalive(Some(Duration::from_secs(60)))
+    .is_ok());
+  assert!(resource.set_keepalive(None).is_ok());
+  resource.close();
+
+  let err = Resource { rid: 0 }.set_nodelay(true).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+#[test]
+fn test_poll_peek() {
+  tokio_util::init(|| {
+    let (client, server) = tcp_loopback_pair();
+    let client = add_tcp_stream(client).unwrap();
+    let server = add_tcp_stream(server).unwrap();
+    tokio_util::block_on(tokio::io::write_all(client.clone(), b"GET")).unwrap();
+    for _ in 0..2 {
+      let mut r = server.clone();
+      let peek = futures::future::poll_fn(move || -> Poll<_, DenoError> {
+        let mut buf = [0u8; 3];
+        let n = try_ready!(r.poll_peek(&mut buf));
+        Ok(Async::Ready(buf[..n].to_vec()))
+      });
+      let peeked = tokio_util::block_on(peek).unwrap();
+      assert_eq!(peeked, b"GET");
+    }
+    client.close();
+    server.close();
+  });
+
+  let err = Resource { rid: 0 }.poll_peek(&mut [0u8; 1]).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+#[test]
+fn test_close() {
+  let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+  let socket = tokio::net::UdpSocket::bind(&addr).unwrap();
+  let resource = add_udp_socket(socket).unwrap();
+  assert!(close(resource.rid).is_ok());
+  assert!(lookup(resource.rid).is_none());
+  // Double close.
+  let err = close(resource.rid).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+#[test]
+fn test_close_unknown_rid() {
+  let err = close(9999).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+fn new_rid() -> DenoResult<ResourceId> {
+  let next_rid = NEXT_RID.fetch_add(1, Ordering::SeqCst);
+  checked_rid(next_rid as u64)
+}
+
+// NEXT_RID is wider than ResourceId, so it never wraps around into ids that
+// are still in use. Refuse to hand out anything past the last valid id.
+fn checked_rid(next_rid: u64) -> DenoResult<ResourceId> {
+  if next_rid > u64::from(ResourceId::max_value()) {
+    return Err(errors::new(
+      errors::ErrorKind::Other,
+      String::from("resource id is exhausted"),
+    ));
+  }
+  Ok(next_rid as ResourceId)
+}
+
+/// Limits how many resources can be open at once. Once the limit is reached,
+/// opening another resource fails with `TooManyOpenFiles` until one is closed.
+/// stdio does not count toward the limit.
+pub fn set_max_resources(limit: usize) {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  table.max_resources = limit;
+}
+
+// Stores `repr` in the table under a newly allocated rid.
+fn insert(table: &mut ResourceTable, repr: Repr) -> DenoResult<ResourceId> {
+  table.check_max_resources()?;
+  let rid = new_rid()?;
+  if table.contains_key(&rid) {
+    return Err(errors::new(
+      errors::ErrorKind::AlreadyExists,
+      String::from("There is already a resource with that rid"),
+    ));
+  }
+  table.insert(rid, repr);
+  Ok(rid)
+}
+
+#[test]
+fn test_checked_rid() {
+  let max = ResourceId::max_value();
+  let max_u64 = u64::from(max);
+  assert_eq!(checked_rid(max_u64 - 1).unwrap(), max - 1);
+  assert_eq!(checked_rid(max_u64).unwrap(), max);
+  let err = checked_rid(max_u64 + 1).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::Other);
+}
+
+#[test]
+fn test_insert_rid_collision() {
+  let mut table = ResourceTable::new();
+  // Occupy the rid that will be handed out next.
+  let next_rid = NEXT_RID.load(Ordering::SeqCst) as ResourceId;
+  for rid in next_rid..next_rid + 100 {
+    table.insert(rid, Repr::Stderr(tokio::io::stderr()));
+  }
+  let err = insert(&mut table, Repr::Stderr(tokio::io::stderr())).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn test_max_resources() {
+  let mut table = ResourceTable::new();
+  table.insert(0, Repr::Stdin(tokio::io::stdin()));
+  table.max_resources = 4;
+  for _ in 0..4 {
+    insert(&mut table, Repr::Stderr(tokio::io::stderr())).unwrap();
+  }
+  let err = insert(&mut table, Repr::Stderr(tokio::io::stderr())).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::TooManyOpenFiles);
+  assert_eq!(table.len(), 4);
+  // Closing a resource makes room for another one.
+  let rid = *table.rids.keys().find(|rid| **rid > 2).unwrap();
+  table.remove(&rid).unwrap();
+  insert(&mut table, Repr::Stderr(tokio::io::stderr())).unwrap();
+}
+
+pub fn add_fs_file(fs_file: tokio::fs::File) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::FsFile(fs_file))?;
+  Ok(Resource { rid })
+}
+
+/// Opens `path` with `options` and adds it as a FsFile resource. On unix,
+/// `mode` sets the permission bits of a newly created file (before the umask
+/// is applied); it is ignored on other platforms.
+pub fn open_file(
+  path: PathBuf,
+  mut options: std::fs::OpenOptions,
+  mode: Option<u32>,
+) -> impl Future<Item = Resource, Error = DenoError> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::OpenOptionsExt;
+    if let Some(mode) = mode {
+      options.mode(mode);
+    }
+  }
+  #[cfg(not(unix))]
+  let _ = mode;
+  tokio::fs::OpenOptions::from(options)
+    .open(path)
+    .map_err(DenoError::from)
+    .and_then(add_fs_file)
+}
+
+#[test]
+fn test_open_file() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("open.txt");
+  std::fs::write(&path, b"hello").unwrap();
+  tokio_util::init(|| {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    let err =
+      tokio_util::block_on(open_file(path.clone(), options, None)).unwrap_err();
+    assert_eq!(err.kind(), errors::ErrorKind::AlreadyExists);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.append(true);
+    let resource =
+      tokio_util::block_on(open_file(path.clone(), options, None)).unwrap();
+    assert_eq!(resource_kind(resource.rid), Some(ResourceKind::FsFile));
+    let (resource, _) =
+      tokio_util::block_on(tokio::io::write_all(resource, b" world")).unwrap();
+    resource.close();
+  });
+  assert_eq!(std::fs::read(&path).unwrap(), b"hello world");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_open_file_mode() {
+  use std::os::unix::fs::PermissionsExt;
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("mode.txt");
+  tokio_util::init(|| {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true);
+    let resource =
+      tokio_util::block_on(open_file(path.clone(), options, Some(0o600)))
+        .unwrap();
+    resource.close();
+  });
+  let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+  assert_eq!(mode & 0o777, 0o600);
+}
+
+pub fn add_fs_dir(read_dir: tokio::fs::ReadDir) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::FsDir(read_dir, None))?;
+  Ok(Resource { rid })
+}
+
+#[test]
+fn test_fs_dir_read_dir() {
+  let dir = tempfile::TempDir::new().unwrap();
+  std::fs::write(dir.path().join("a.txt"), b"hello").unwrap();
+  std::fs::create_dir(dir.path().join("b")).unwrap();
+  let path = dir.path().to_owned();
+  tokio_util::init(|| {
+    let read_dir = tokio_util::block_on(tokio::fs::read_dir(path)).unwrap();
+    let resource = add_fs_dir(read_dir).unwrap();
+    assert_eq!(get_type(resource.rid), Some(String::from("fsDir")));
+    let mut dir_resource = resource.clone();
+    let mut entries = tokio_util::block_on(
+      futures::stream::poll_fn(move || dir_resource.poll_read_dir()).collect(),
+    )
+    .unwrap();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].name, "a.txt");
+    assert!(entries[0].file_type.is_file());
+    assert_eq!(entries[0].len, 5);
+    assert_eq!(entries[1].name, "b");
+    assert!(entries[1].file_type.is_dir());
+    resource.close();
+  });
+
+  // Other kinds of resources are an error, not a panic.
+  let err = Resource { rid: 0 }.poll_read_dir().unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+pub fn add_tcp_listener(
+  listener: tokio::net::TcpListener,
+) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::TcpListener(listener, None))?;
+  Ok(Resource { rid })
+}
+
+// The backlog of listeners bound by `bind_tcp_listener()`.
+const TCP_LISTEN_BACKLOG: i32 = 1024;
+
+/// Binds a TCP listener on `addr` with SO_REUSEADDR set. With `reuse_port`,
+/// SO_REUSEPORT is set too so that several listeners can share the address.
+pub fn bind_tcp_listener(
+  addr: SocketAddr,
+  reuse_port: bool,
+) -> DenoResult<Resource> {
+  bind_tcp(addr, reuse_port, TCP_LISTEN_BACKLOG)
+}
+
+/// Like `bind_tcp_listener()`, but queues up to `backlog` connections that
+/// have not been accepted yet. The OS may cap the value.
+pub fn bind_tcp_listener_with_backlog(
+  addr: SocketAddr,
+  backlog: i32,
+) -> DenoResult<Resource> {
+  bind_tcp(addr, false, backlog)
+}
+
+fn bind_tcp(
+  addr: SocketAddr,
+  reuse_port: bool,
+  backlog: i32,
+) -> DenoResult<Resource> {
+  let builder = match addr {
+    SocketAddr::V4(_) => TcpBuilder::new_v4()?,
+    SocketAddr::V6(_) => TcpBuilder::new_v6()?,
+  };
+  builder.reuse_address(true)?;
+  if reuse_port {
+    set_reuse_port(&builder)?;
+  }
+  let listener = builder.bind(addr)?.listen(backlog)?;
+  let listener = tokio::net::TcpListener::from_std(
+    listener,
+    &tokio::reactor::Handle::default(),
+  )?;
+  add_tcp_listener(listener)
+}
+
+#[cfg(unix)]
+fn set_reuse_port(builder: &TcpBuilder) -> DenoResult<()> {
+  builder.reuse_port(true)?;
+  Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_reuse_port(_builder: &TcpBuilder) -> DenoResult<()> {
+  Err(errors::new(
+    errors::ErrorKind::Other,
+    "SO_REUSEPORT is not supported on this platform".to_string(),
+  ))
+}
+
+#[cfg(unix)]
+#[test]
+fn test_bind_tcp_listener_reuse_port() {
+  let addr = "127.0.0.1:0".parse().unwrap();
+  let mut first = bind_tcp_listener(addr, true).unwrap();
+  let local_addr = match RESOURCE_TABLE.lock().unwrap().get(&first.rid) {
+    Some(Repr::TcpListener(l, _)) => l.local_addr().unwrap(),
+    _ => panic!("expected a tcp listener"),
+  };
+  let mut second = bind_tcp_listener(local_addr, true).unwrap();
+  assert_eq!(resource_kind(second.rid), Some(ResourceKind::TcpListener));
+  second.close();
+  first.close();
+}
+
+#[test]
+fn test_bind_tcp_listener_with_backlog() {
+  let addr = "127.0.0.1:0".parse().unwrap();
+  let mut listener = bind_tcp_listener_with_backlog(addr, 1024).unwrap();
+  let local_addr = match RESOURCE_TABLE.lock().unwrap().get(&listener.rid) {
+    Some(Repr::TcpListener(l, _)) => l.local_addr().unwrap(),
+    _ => panic!("expected a tcp listener"),
+  };
+  let client = std::net::TcpStream::connect(local_addr).unwrap();
+  let (rid, addr) =
+    futures::future::poll_fn(|| listener.poll_accept_resource())
+      .wait()
+      .unwrap();
+  assert_eq!(addr, client.local_addr().unwrap());
+  close(rid).unwrap();
+  listener.close();
+}
+
+#[test]
+fn test_poll_accept_resource() {
+  let addr = "127.0.0.1:0".parse().unwrap();
+  let listener = tokio::net::TcpListener::bind(&addr).unwrap();
+  let local_addr = listener.local_addr().unwrap();
+  let mut listener_resource = add_tcp_listener(listener).unwrap();
+
+  let client = std::net::TcpStream::connect(local_addr).unwrap();
+  let (rid, addr) =
+    futures::future::poll_fn(|| listener_resource.poll_accept_resource())
+      .wait()
+      .unwrap();
+  assert_eq!(addr, client.local_addr().unwrap());
+  assert_eq!(resource_kind(rid), Some(ResourceKind::TcpStream));
+  close(rid).unwrap();
+  listener_resource.close();
+
+  // Accepting on something that isn't a listener is an error, not a panic.
+  let mut stdin = Resource { rid: 0 };
+  let err = futures::future::poll_fn(|| stdin.poll_accept_resource())
+    .wait()
+    .unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_connection_alive() {
+  let (local, peer) = tcp_loopback_pair();
+  let mut resource = add_tcp_stream(local).unwrap();
+  assert!(resource.connection_alive().unwrap());
+
+  // Pending data is not consumed by the check.
+  let (peer, _) = tokio::io::write_all(peer, b"x").wait().unwrap();
+  std::thread::sleep(Duration::from_millis(50));
+  assert!(resource.connection_alive().unwrap());
+  let mut buf = [0u8; 4];
+  let nread = futures::future::poll_fn(|| resource.poll_read(&mut buf))
+    .wait()
+    .unwrap();
+  assert_eq!(&buf[..nread], b"x");
+
+  drop(peer);
+  // The FIN may take a moment to arrive.
+  let mut alive = true;
+  for _ in 0..100 {
+    alive = resource.connection_alive().unwrap();
+    if !alive {
+      break;
+    }
+    std::thread::sleep(Duration::from_millis(10));
+  }
+  assert!(!alive);
+  resource.close();
+  assert!(resource.connection_alive().is_err());
+}
+
+/// Returns the first of `rids` that is ready to be read from, see
+/// `Resource::poll_read_ready()`. The search starts at `*cursor`, which is
+/// then moved past the returned rid, so that a busy resource can't starve the
+/// ones after it. `NotReady` means none of them is ready; the current task
+/// is woken when that changes.
+pub fn poll_ready_round_robin(
+  cursor: &mut usize,
+  rids: &[ResourceId],
+) -> Poll<ResourceId, Error> {
+  if rids.is_empty() {
+    // Nothing could ever wake the task.
+    return Err(Error::new(
+      std::io::ErrorKind::InvalidInput,
+      "No rids given",
+    ));
+  }
+  for i in 0..rids.len() {
+    let index = (*cursor + i) % rids.len();
+    let mut resource = Resource { rid: rids[index] };
+    if let Async::Ready(()) = resource.poll_read_ready()? {
+      *cursor = (index + 1) % rids.len();
+      return Ok(Async::Ready(resource.rid));
+    }
+  }
+  Ok(Async::NotReady)
+}
+
+#[test]
+fn test_poll_ready_round_robin() {
+  let pipes: Vec<(Resource, Resource)> =
+    (0..3).map(|_| new_pipe().unwrap()).collect();
+  let rids: Vec<ResourceId> = pipes.iter().map(|(r, _)| r.rid).collect();
+  let mut cursor = 0;
+  // Nothing has been written yet.
+  let r = futures::future::lazy(|| {
+    Ok::<_, ()>(poll_ready_round_robin(&mut cursor, &rids).unwrap())
+  }).wait()
+  .unwrap();
+  assert_eq!(r, Async::NotReady);
+
+  for (_, writer) in pipes.iter() {
+    tokio::io::write_all(writer.clone(), b"x").wait().unwrap();
+  }
+  // Nothing is read, so all of them stay ready and each gets its turn.
+  for expected in [0, 1, 2, 0].iter() {
+    let rid =
+      futures::future::poll_fn(|| poll_ready_round_robin(&mut cursor, &rids))
+        .wait()
+        .unwrap();
+    assert_eq!(rid, rids[*expected]);
+  }
+
+  for (reader, writer) in pipes {
+    reader.close();
+    writer.close();
+  }
+}
+
+#[test]
+fn test_poll_read_ready_tcp_stream() {
+  let (local, peer) = tcp_loopback_pair();
+  let mut resource = add_tcp_stream(local).unwrap();
+  tokio::io::write_all(peer, b"hello").wait().unwrap();
+  futures::future::poll_fn(|| resource.poll_read_ready())
+    .wait()
+    .unwrap();
+  // Nothing was consumed by the readiness check.
+  let mut buf = [0u8; 8];
+  let nread = futures::future::poll_fn(|| resource.poll_read(&mut buf))
+    .wait()
+    .unwrap();
+  assert_eq!(&buf[..nread], b"hello");
+  resource.close();
+}
+
+#[test]
+fn test_accept_until() {
+  let addr = "127.0.0.1:0".parse().unwrap();
+  let listener = tokio::net::TcpListener::bind(&addr).unwrap();
+  let local_addr = listener.local_addr().unwrap();
+  let listener_resource = add_tcp_listener(listener).unwrap();
+
+  tokio_util::init(|| {
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(100);
+    let conns =
+      tokio_util::block_on(listener_resource.accept_until(deadline).collect())
+        .unwrap();
+    assert!(conns.is_empty());
+    assert!(Instant::now() >= deadline);
+
+    let client = std::net::TcpStream::connect(local_addr).unwrap();
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let conns =
+      tokio_util::block_on(listener_resource.accept_until(deadline).collect())
+        .unwrap();
+    assert_eq!(conns.len(), 1);
+    assert_eq!(conns[0].1, client.local_addr().unwrap());
+    close(conns[0].0).unwrap();
+  });
+  listener_resource.close();
+}
+
+#[test]
+fn test_poll_accept_batch() {
+  let addr = "127.0.0.1:0".parse().unwrap();
+  let listener = tokio::net::TcpListener::bind(&addr).unwrap();
+  let local_addr = listener.local_addr().unwrap();
+  let mut listener_resource = add_tcp_listener(listener).unwrap();
+
+  let clients: Vec<_> = (0..3)
+    .map(|_| std::net::TcpStream::connect(local_addr).unwrap())
+    .collect();
+  // Give the kernel a moment to complete all handshakes.
+  std::thread::sleep(std::time::Duration::from_millis(50));
+
+  let batch =
+    futures::future::poll_fn(|| listener_resource.poll_accept_batch(10))
+      .wait()
+      .unwrap();
+  assert_eq!(batch.len(), 3);
+  let mut peers: Vec<_> = batch.iter().map(|(_, addr)| *addr).collect();
+  let mut expected: Vec<_> =
+    clients.iter().map(|c| c.local_addr().unwrap()).collect();
+  peers.sort();
+  expected.sort();
+  assert_eq!(peers, expected);
+  for (rid, _) in batch {
+    assert_eq!(resource_kind(rid), Some(ResourceKind::TcpStream));
+    close(rid).unwrap();
+  }
+  listener_resource.close();
+
+  let mut stdin = Resource { rid: 0 };
+  let err = futures::future::poll_fn(|| stdin.poll_accept_batch(10))
+    .wait()
+    .unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+pub fn add_tcp_stream(stream: tokio::net::TcpStream) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::TcpStream(stream))?;
+  Ok(Resource { rid })
+}
+
+pub fn add_udp_socket(socket: tokio::net::UdpSocket) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::UdpSocket(socket))?;
+  Ok(Resource { rid })
+}
+
+#[test]
+fn test_udp_socket_send_recv() {
+  let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+  let socket = tokio::net::UdpSocket::bind(&addr).unwrap();
+  let local_addr = socket.local_addr().unwrap();
+  let mut resource = add_udp_socket(socket).unwrap();
+
+  let nsent =
+    futures::future::poll_fn(|| resource.poll_send_to(b"hello", &local_addr))
+      .wait()
+      .unwrap();
+  assert_eq!(nsent, 5);
+
+  let mut buf = [0u8; 16];
+  let (nrecv, peer) =
+    futures::future::poll_fn(|| resource.poll_recv_from(&mut buf))
+      .wait()
+      .unwrap();
+  assert_eq!(&buf[..nrecv], b"hello");
+  assert_eq!(peer, local_addr);
+  resource.close();
+
+  // Other kinds of resources are an error, not a panic.
+  let mut stdin = Resource { rid: 0 };
+  let err = stdin.poll_send_to(b"hello", &local_addr).unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+  let err = stdin.poll_recv_from(&mut buf).unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+pub fn add_tls_stream(
+  stream: tokio_rustls::client::TlsStream<TcpStream>,
+) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::TlsStream(Box::new(stream)))?;
+  Ok(Resource { rid })
+}
+
+pub fn add_server_tls_stream(
+  stream: tokio_rustls::server::TlsStream<TcpStream>,
+) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::ServerTlsStream(Box::new(stream)))?;
+  Ok(Resource { rid })
+}
+
+#[test]
+fn test_tls_stream_round_trip() {
+  use rustls::internal::pemfile;
+  use std::io::BufReader;
+  use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+  // Self-signed certificate for "localhost".
+  let cert_pem = include_bytes!("../tests/tls/localhost.crt");
+  let key_pem = include_bytes!("../tests/tls/localhost.key");
+  let certs = pemfile::certs(&mut BufReader::new(&cert_pem[..])).unwrap();
+  let mut keys =
+    pemfile::pkcs8_private_keys(&mut BufReader::new(&key_pem[..])).unwrap();
+
+  let mut server_config =
+    rustls::ServerConfig::new(rustls::NoClientAuth::new());
+  server_config
+    .set_single_cert(certs.clone(), keys.remove(0))
+    .unwrap();
+  let mut client_config = rustls::ClientConfig::new();
+  client_config.root_store.add(&certs[0]).unwrap();
+  let acceptor = TlsAcceptor::from(Arc::new(server_config));
+  let connector = TlsConnector::from(Arc::new(client_config));
+
+  let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+  let listener = tokio::net::TcpListener::bind(&addr).unwrap();
+  let addr = listener.local_addr().unwrap();
+
+  // Echo the first 5 bytes back to the client.
+  let server = listener
+    .incoming()
+    .into_future()
+    .map_err(|(e, _)| e)
+    .and_then(move |(stream, _)| acceptor.accept(stream.unwrap()))
+    .and_then(|tls| tokio::io::read_exact(tls, [0u8; 5]))
+    .and_then(|(tls, buf)| tokio::io::write_all(tls, buf));
+
+  let domain = webpki::DNSNameRef::try_from_ascii_str("localhost").unwrap();
+  let client = TcpStream::connect(&addr)
+    .and_then(move |tcp_stream| connector.connect(domain, tcp_stream))
+    .and_then(|tls| {
+      let resource = add_tls_stream(tls).unwrap();
+      assert_eq!(get_type(resource.rid), Some(String::from("tlsStream")));
+      tokio::io::write_all(resource, b"hello")
+    }).and_then(|(resource, _)| tokio::io::read_exact(resource, [0u8; 5]))
+    .map(|(mut resource, buf)| {
+      resource.shutdown(Shutdown::Write).unwrap();
+      resource.close();
+      buf
+    });
+
+  let mut rt = tokio::runtime::Runtime::new().unwrap();
+  let (_, buf) = rt.block_on(server.join(client)).unwrap();
+  assert_eq!(&buf, b"hello");
+}
+
+#[cfg(unix)]
+pub fn add_unix_listener(
+  listener: tokio::net::UnixListener,
+) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::UnixListener(listener))?;
+  Ok(Resource { rid })
+}
+
+#[cfg(unix)]
+pub fn add_unix_stream(stream: tokio::net::UnixStream) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::UnixStream(stream))?;
+  Ok(Resource { rid })
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_listener_accept() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("test.sock");
+  let listener = tokio::net::UnixListener::bind(&path).unwrap();
+  let mut listener_resource = add_unix_listener(listener).unwrap();
+  assert_eq!(
+    get_type(listener_resource.rid),
+    Some(String::from("unixListener"))
+  );
+
+  let _client = std::os::unix::net::UnixStream::connect(&path).unwrap();
+  let accepted = futures::future::poll_fn(|| listener_resource.poll_accept())
+    .wait()
+    .unwrap();
+  let stream_resource = match accepted {
+    Accepted::Unix(resource, _) => resource,
+    _ => panic!("expected a unix stream"),
+  };
+  assert_eq!(
+    get_type(stream_resource.rid),
+    Some(String::from("unixStream"))
+  );
+  stream_resource.close();
+  listener_resource.close();
+}
+
+/// Registers a file descriptor obtained elsewhere, e.g. inherited through
+/// systemd-style socket activation. `kind` tells what the fd refers to:
+/// FsFile, TcpStream, TcpListener or UnixStream. For other kinds an error is
+/// returned and the fd is left alone; otherwise the table takes ownership of
+/// it, and it is closed when the resource is closed or fails to register.
+#[cfg(unix)]
+pub fn add_raw_fd(
+  fd: std::os::unix::io::RawFd,
+  kind: ResourceKind,
+) -> DenoResult<Resource> {
+  let handle = tokio::reactor::Handle::default();
+  let repr = match kind {
+    ResourceKind::FsFile => {
+      let file = unsafe { std::fs::File::from_raw_fd(fd) };
+      Repr::FsFile(tokio::fs::File::from_std(file))
+    }
+    ResourceKind::TcpStream => {
+      let stream = unsafe { std::net::TcpStream::from_raw_fd(fd) };
+      Repr::TcpStream(TcpStream::from_std(stream, &handle)?)
+    }
+    ResourceKind::TcpListener => {
+      let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+      let listener = tokio::net::TcpListener::from_std(listener, &handle)?;
+      Repr::TcpListener(listener, None)
+    }
+    ResourceKind::UnixStream => {
+      let stream = unsafe { std::os::unix::net::UnixStream::from_raw_fd(fd) };
+      Repr::UnixStream(tokio::net::UnixStream::from_std(stream, &handle)?)
+    }
+    _ => {
+      return Err(errors::new(
+        errors::ErrorKind::InvalidInput,
+        format!("Cannot register a raw fd as {}", kind.as_str()),
+      ))
+    }
+  };
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, repr)?;
+  Ok(Resource { rid })
+}
+
+#[cfg(unix)]
+#[test]
+fn test_add_raw_fd() {
+  use std::os::unix::io::{AsRawFd, IntoRawFd};
+  let (a, mut b) = std::os::unix::net::UnixStream::pair().unwrap();
+  let resource = add_raw_fd(a.into_raw_fd(), ResourceKind::UnixStream).unwrap();
+  assert_eq!(resource_kind(resource.rid), Some(ResourceKind::UnixStream));
+  let (resource, _) = tokio::io::write_all(resource, b"hello").wait().unwrap();
+  let mut buf = [0u8; 5];
+  b.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf, b"hello");
+  resource.close();
+
+  let (c, _d) = std::os::unix::net::UnixStream::pair().unwrap();
+  let err = add_raw_fd(c.as_raw_fd(), ResourceKind::Timer).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::InvalidInput);
+}
+
+/// Starts listening for `signum`. Use `Resource::poll_signal()` to wait for
+/// it to be delivered.
+#[cfg(unix)]
+pub fn add_signal(signum: i32) -> DenoResult<Resource> {
+  // The future resolves immediately; it only exists to pick up a reactor.
+  let signal = tokio_signal::unix::Signal::new(signum).wait()?;
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::Signal(signal))?;
+  Ok(Resource { rid })
+}
+
+#[cfg(unix)]
+#[test]
+fn test_signal() {
+  let mut resource = add_signal(libc::SIGUSR1).unwrap();
+  assert_eq!(get_type(resource.rid), Some(String::from("signal")));
+  unsafe { libc::raise(libc::SIGUSR1) };
+  let signum = futures::future::poll_fn(|| resource.poll_signal())
+    .wait()
+    .unwrap();
+  assert_eq!(signum, Some(libc::SIGUSR1));
+  resource.close();
+}
+
+/// Watches `paths` for changes. Closing the resource stops the watch.
+#[cfg(target_os = "linux")]
+pub fn add_fs_watcher(
+  paths: Vec<PathBuf>,
+  recursive: bool,
+) -> DenoResult<Resource> {
+  let watcher = FsWatcher::new(&paths, recursive)?;
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::FsWatcher(Box::new(watcher)))?;
+  Ok(Resource { rid })
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_fs_watcher() {
+  use crate::fs_watcher::FsEventKind;
+  let dir = tempfile::TempDir::new().unwrap();
+  let mut resource =
+    add_fs_watcher(vec![dir.path().to_path_buf()], false).unwrap();
+  assert_eq!(get_type(resource.rid), Some(String::from("fsWatcher")));
+
+  let file_path = dir.path().join("a.txt");
+  std::fs::write(&file_path, b"hello").unwrap();
+  let event = futures::future::poll_fn(|| resource.poll_fs_event())
+    .wait()
+    .unwrap();
+  assert_eq!(event.path, file_path);
+  assert_eq!(event.kind, FsEventKind::Create);
+  resource.close();
+}
+
+fn timer_error(err: tokio::timer::Error) -> DenoError {
+  errors::new(errors::ErrorKind::Other, err.to_string())
+}
+
+/// Creates a timer resource that fires once, after `dur`.
+pub fn add_timer(dur: Duration) -> DenoResult<Resource> {
+  let delay = tokio::timer::Delay::new(Instant::now() + dur);
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::Timer(delay, None))?;
+  Ok(Resource { rid })
+}
+
+/// Creates an interval resource that fires every `dur`, starting `dur` from
+/// now.
+pub fn add_interval(dur: Duration) -> DenoResult<Resource> {
+  let interval = tokio::timer::Interval::new(Instant::now() + dur, dur);
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::Interval(interval, None))?;
+  Ok(Resource { rid })
+}
+
+#[test]
+fn test_timer() {
+  tokio_util::init(|| {
+    let timer = add_timer(Duration::from_millis(50)).unwrap();
+    assert_eq!(get_type(timer.rid), Some(String::from("timer")));
+    let start = Instant::now();
+    let mut t = timer.clone();
+    tokio_util::block_on(futures::future::poll_fn(move || t.poll_timer()))
+      .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    timer.close();
+  });
+}
+
+#[test]
+fn test_timer_closed_early() {
+  tokio_util::init(|| {
+    let timer = add_timer(Duration::from_secs(10)).unwrap();
+    let mut t = timer.clone();
+    let (tx, rx) = futures::sync::oneshot::channel();
+    tokio::spawn(futures::future::poll_fn(move || t.poll_timer()).then(
+      move |r| {
+        tx.send(r.is_err()).unwrap();
+        Ok(())
+      },
+    ));
+    std::thread::sleep(Duration::from_millis(50));
+    timer.close();
+    // The waiting task is woken up with an error instead of the timer firing.
+    assert!(rx.wait().unwrap());
+  });
+}
+
+#[test]
+fn test_interval() {
+  tokio_util::init(|| {
+    let interval = add_interval(Duration::from_millis(10)).unwrap();
+    assert_eq!(get_type(interval.rid), Some(String::from("interval")));
+    let mut i = interval.clone();
+    let mut ticks = 0;
+    let two_ticks = futures::future::poll_fn(move || -> Poll<_, DenoError> {
+      while ticks < 2 {
+        try_ready!(i.poll_interval());
+        ticks += 1;
+      }
+      Ok(Async::Ready(()))
+    });
+    tokio_util::block_on(two_ticks).unwrap();
+    interval.close();
+    let mut i = interval.clone();
+    let r = futures::future::poll_fn(move || i.poll_interval()).wait();
+    assert_eq!(r.unwrap_err().kind(), errors::ErrorKind::BadResource);
+  });
+}
+
+/// Creates a DNS query resource that looks up `host` when it is polled, see
+/// `poll_dns()`. The addresses found each have `port` set.
+pub fn add_dns_query(host: &str, port: u16) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::DnsQuery(host.to_string(), port, None))?;
+  Ok(Resource { rid })
+}
+
+#[test]
+fn test_dns_query() {
+  tokio_util::init(|| {
+    let query = add_dns_query("localhost", 4545).unwrap();
+    assert_eq!(get_type(query.rid), Some(String::from("dnsQuery")));
+    let mut q = query.clone();
+    let addrs =
+      tokio_util::block_on(futures::future::poll_fn(move || q.poll_dns()))
+        .unwrap();
+    let expected: SocketAddr = "127.0.0.1:4545".parse().unwrap();
+    assert!(addrs.contains(&expected));
+    query.close();
+
+    // A closed query no longer resolves.
+    let query = add_dns_query("localhost", 80).unwrap();
+    query.close();
+    let mut q = query.clone();
+    let r =
+      tokio_util::block_on(futures::future::poll_fn(move || q.poll_dns()));
+    assert_eq!(r.unwrap_err().kind(), errors::ErrorKind::BadResource);
+  });
+}
+
+pub fn add_hyper_body(body: hyper::Body) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let body = HttpBody::from(body);
+  let rid = insert(&mut tg, Repr::HttpBody(body))?;
+  Ok(Resource { rid })
+}
+
+#[test]
+fn test_http_body_content_length_and_trailers() {
+  let resource = add_hyper_body(hyper::Body::from("hello world")).unwrap();
+  assert_eq!(resource.http_content_length().unwrap(), Some(11));
+  let (mut resource, buf) = poll_read_to_end(resource).wait().unwrap();
+  assert_eq!(buf, b"hello world");
+  let trailers = futures::future::poll_fn(|| resource.poll_trailers())
+    .wait()
+    .unwrap();
+  assert!(trailers.is_none());
+  resource.close();
+
+  let resource = Resource { rid: 0 };
+  let err = resource.http_content_length().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+/// Future returned by `Resource::read_to_end_capped()`.
+pub struct ReadToEndCapped {
+  resource: Resource,
+  buf: Vec<u8>,
+  limit: usize,
+  // Whether the resource has been found to be readable by this future.
+  checked: bool,
+}
+
+impl Future for ReadToEndCapped {
+  type Item = Vec<u8>;
+  type Error = DenoError;
+
+  fn poll(&mut self) -> Poll<Vec<u8>, DenoError> {
+    if !self.checked {
+      let table = RESOURCE_TABLE.lock().unwrap();
+      match table.get(&self.resource.rid) {
+        Some(Repr::HttpBody(ref body)) => {
+          // Don't let a bogus length allocate more than the limit.
+          let hint = std::cmp::min(body.capacity_hint(), self.limit);
+          self.buf.reserve_exact(hint);
+        }
+        Some(Repr::FsFile(_)) => {}
+        _ => return Err(bad_resource()),
+      }
+      self.checked = true;
+    }
+    let mut chunk = [0u8; 4096];
+    loop {
+      let n = try_ready!(self.resource.poll_read(&mut chunk));
+      if n == 0 {
+        let buf = std::mem::replace(&mut self.buf, Vec::new());
+        return Ok(Async::Ready(buf));
+      }
+      if self.buf.len() + n > self.limit {
+        self.buf = Vec::new();
+        return Err(errors::new(
+          errors::ErrorKind::TooLarge,
+          format!("Resource is larger than {} bytes", self.limit),
+        ));
+      }
+      self.buf.extend_from_slice(&chunk[..n]);
+    }
+  }
+}
+
+#[test]
+fn test_read_to_end_capped() {
+  let body = hyper::Body::from(vec![b'x'; 100]);
+  let resource = add_hyper_body(body).unwrap();
+  let err = resource.read_to_end_capped(10).wait().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::TooLarge);
+  resource.close();
+
+  let body = hyper::Body::from(vec![b'x'; 100]);
+  let resource = add_hyper_body(body).unwrap();
+  let buf = resource.read_to_end_capped(100).wait().unwrap();
+  assert_eq!(buf, vec![b'x'; 100]);
+  resource.close();
+
+  let resource = Resource { rid: 0 };
+  let err = resource.read_to_end_capped(10).wait().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+#[test]
+fn test_http_hint_capacity() {
+  let data: Vec<u8> = (0..100_000).map(|i| (i % 256) as u8).collect();
+  let chunks: Vec<Vec<u8>> = data.chunks(1000).map(|c| c.to_vec()).collect();
+  // A streamed body has no declared length.
+  let stream = futures::stream::iter_ok::<_, std::io::Error>(chunks);
+  let body = hyper::Body::wrap_stream(stream);
+  let mut resource = add_hyper_body(body).unwrap();
+  assert_eq!(resource.http_capacity_hint().unwrap(), 0);
+  resource.http_hint_capacity(data.len()).unwrap();
+  assert_eq!(resource.http_capacity_hint().unwrap(), data.len());
+
+  let buf = resource.read_to_end_capped(1 << 20).wait().unwrap();
+  assert_eq!(buf, data);
+  // Allocated once, up front.
+  assert_eq!(buf.capacity(), data.len());
+  resource.close();
+
+  let mut stdin = Resource { rid: 0 };
+  let err = stdin.http_hint_capacity(10).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+// Number of writes a pipe created by new_pipe() buffers before the writer
+// has to wait for the reader.
+const PIPE_CAPACITY: usize = 16;
+
+/// Creates an in-memory pipe. Returns the (reader, writer) resources.
+pub fn new_pipe() -> DenoResult<(Resource, Resource)> {
+  let (reader, writer) = mem_pipe::pipe(PIPE_CAPACITY);
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let reader_rid = insert(&mut tg, Repr::MemPipeReader(reader))?;
+  let writer_rid = insert(&mut tg, Repr::MemPipeWriter(writer))?;
+  Ok((Resource { rid: reader_rid }, Resource { rid: writer_rid }))
+}
+
+#[test]
+fn test_write_to_closed_pipe_keeps_io_kind() {
+  let (reader, mut writer) = new_pipe().unwrap();
+  reader.close();
+  let err = futures::future::poll_fn(|| writer.poll_write(b"abc"))
+    .wait()
+    .unwrap_err();
+  let err = DenoError::from(err);
+  assert_eq!(err.kind(), errors::ErrorKind::BrokenPipe);
+  assert_eq!(err.io_kind(), Some(std::io::ErrorKind::BrokenPipe));
+  assert_eq!(bad_resource().io_kind(), None);
+  writer.close();
+}
+
+#[test]
+fn test_mem_pipe() {
+  let (mut reader, mut writer) = new_pipe().unwrap();
+  let n = futures::future::poll_fn(|| writer.poll_write(b"abc"))
+    .wait()
+    .unwrap();
+  assert_eq!(n, 3);
+
+  let mut buf = [0u8; 8];
+  let n = futures::future::poll_fn(|| reader.poll_read(&mut buf))
+    .wait()
+    .unwrap();
+  assert_eq!(&buf[..n], b"abc");
+
+  // Closing the writer signals EOF to the reader.
+  writer.close();
+  let n = futures::future::poll_fn(|| reader.poll_read(&mut buf))
+    .wait()
+    .unwrap();
+  assert_eq!(n, 0);
+  reader.close();
+}
+
+#[test]
+fn test_poll_flush() {
+  let (mut reader, mut writer) = new_pipe().unwrap();
+  let n = futures::future::poll_fn(|| writer.poll_write(b"abc"))
+    .wait()
+    .unwrap();
+  assert_eq!(n, 3);
+  futures::future::poll_fn(|| writer.poll_flush())
+    .wait()
+    .unwrap();
+
+  let mut buf = [0u8; 8];
+  let n = futures::future::poll_fn(|| reader.poll_read(&mut buf))
+    .wait()
+    .unwrap();
+  assert_eq!(&buf[..n], b"abc");
+
+  writer.close();
+  reader.close();
+  let err = futures::future::poll_fn(|| writer.poll_flush())
+    .wait()
+    .unwrap_err();
+  assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+pub fn add_repl(repl: Repl) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::Repl(Arc::new(Mutex::new(repl))))?;
+  Ok(Resource { rid })
+}
+
+/// Creates a repl resource whose history is loaded from `history_file` and
+/// capped at `max_history` lines. Use `Resource::save_repl_history()` to
+/// write it back.
+pub fn add_repl_with_history(
+  history_file: PathBuf,
+  max_history: usize,
+) -> DenoResult<Resource> {
+  add_repl(Repl::with_max_history(history_file, max_history))
+}
+
+#[test]
+fn test_repl_history() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let history_file = dir.path().join("repl_history.txt");
+  let resource = add_repl_with_history(history_file.clone(), 2).unwrap();
+  {
+    let repl = get_repl(resource.rid).unwrap();
+    let mut repl = repl.lock().unwrap();
+    repl.add_history_entry("a");
+    repl.add_history_entry("b");
+    repl.add_history_entry("b");
+    repl.add_history_entry("c");
+  }
+  resource.save_repl_history().unwrap();
+  resource.close();
+
+  let resource = add_repl_with_history(history_file, 2).unwrap();
+  let repl = get_repl(resource.rid).unwrap();
+  assert_eq!(repl.lock().unwrap().history(), vec!["b", "c"]);
+  resource.close();
+}
+
+pub fn add_worker(wc: WorkerChannels) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::Worker(wc))?;
+  Ok(Resource { rid })
+}
+
+pub fn worker_post_message(
+  rid: ResourceId,
+  buf: Buf,
+) -> futures::sink::Send<futures::sync::mpsc::Sender<Buf>> {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  let maybe_repr = table.get_mut(&rid);
+  match maybe_repr {
+    Some(Repr::Worker(ref mut wc)) => {
+      // unwrap here is incorrect, but doing it anyway
+      wc.0.clone().send(buf)
+    }
+    _ => panic!("bad resource"), // futures::future::err(bad_resource()).into(),
+  }
+}
+
+pub struct WorkerReceiver {
+  rid: ResourceId,
+}
+
+// Invert the dumbness that tokio_process causes by making Child itself a future.
+impl Future for WorkerReceiver {
+  type Item = Option<Buf>;
+  type Error = DenoError;
+
+  fn poll(&mut self) -> Poll<Option<Buf>, DenoError> {
+    let mut table = RESOURCE_TABLE.lock().unwrap();
+    let maybe_repr = table.get_mut(&self.rid);
+    match maybe_repr {
+      Some(Repr::Worker(ref mut wc)) => wc.1.poll().map_err(|()| {
+        errors::new(errors::ErrorKind::Other, "recv msg error".to_string())
+      }),
+      _ => Err(bad_resource()),
+    }
+  }
+}
+
+pub fn worker_recv_message(rid: ResourceId) -> WorkerReceiver {
+  WorkerReceiver { rid }
 }
 
 #[cfg_attr(feature = "cargo-clippy", allow(stutter))]
 pub struct ChildResources {
   pub child_rid: ResourceId,
-  pub stdin_rid: Option<ResourceId>,
-  pub stdout_rid: Option<ResourceId>,
-  pub stderr_rid: Option<ResourceId>,
+  pub pid: u32,
+  pub stdin: StdioDisposition,
+  pub stdout: StdioDisposition,
+  pub stderr: StdioDisposition,
+}
+
+/// What became of a stdio stream of a child.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StdioDisposition {
+  /// The child shares the stream with this process.
+  Inherited,
+  /// The child's end of a pipe, registered under the given rid. The caller
+  /// must close it.
+  Piped(ResourceId),
+  /// The stream is connected to /dev/null (NUL on windows).
+  Null,
+}
+
+impl StdioDisposition {
+  fn new(requested: ProcessStdio, rid: Option<ResourceId>) -> Self {
+    match (rid, requested) {
+      (Some(rid), _) => StdioDisposition::Piped(rid),
+      (None, ProcessStdio::Null) => StdioDisposition::Null,
+      (None, _) => StdioDisposition::Inherited,
+    }
+  }
+
+  pub fn rid(self) -> Option<ResourceId> {
+    match self {
+      StdioDisposition::Piped(rid) => Some(rid),
+      _ => None,
+    }
+  }
 }
 
-pub fn add_child(mut c: tokio_process::Child) -> ChildResources {
-  let child_rid = new_rid();
+/// Adds a child and its piped stdio streams to the resource table. `stdin`,
+/// `stdout` and `stderr` are the modes the child was spawned with.
+pub fn add_child(
+  mut c: tokio_process::Child,
+  stdin: ProcessStdio,
+  stdout: ProcessStdio,
+  stderr: ProcessStdio,
+) -> DenoResult<ChildResources> {
   let mut tg = RESOURCE_TABLE.lock().unwrap();
 
-  let mut resources = ChildResources {
+  let stdio = vec![
+    c.stdin().take().map(Repr::ChildStdin),
+    c.stdout().take().map(Repr::ChildStdout),
+    c.stderr().take().map(Repr::ChildStderr),
+  ];
+  let pid = c.id();
+  // The child gets its rid before its stdio streams.
+  let child_rid =
+    insert(&mut tg, Repr::Child(Box::new(c), Default::default()))?;
+
+  let mut stdio_rids = Vec::new();
+  for repr in stdio {
+    let rid = match repr.map(|repr| insert(&mut tg, repr)) {
+      None => None,
+      Some(Ok(rid)) => Some(rid),
+      Some(Err(err)) => {
+        // Don't leave the child or the streams added so far behind.
+        for rid in stdio_rids.iter().filter_map(|rid| *rid) {
+          tg.remove(&rid);
+        }
+        tg.remove(&child_rid);
+        return Err(err);
+      }
+    };
+    stdio_rids.push(rid);
+  }
+  let (stdin_rid, stdout_rid, stderr_rid) =
+    (stdio_rids[0], stdio_rids[1], stdio_rids[2]);
+
+  Ok(ChildResources {
     child_rid,
-    stdin_rid: None,
-    stdout_rid: None,
-    stderr_rid: None,
-  };
+    pid,
+    stdin: StdioDisposition::new(stdin, stdin_rid),
+    stdout: StdioDisposition::new(stdout, stdout_rid),
+    stderr: StdioDisposition::new(stderr, stderr_rid),
+  })
+}
 
-  if c.stdin().is_some() {
-    let stdin = c.stdin().take().unwrap();
-    let rid = new_rid();
-    let r = tg.insert(rid, Repr::ChildStdin(stdin));
-    assert!(r.is_none());
-    resources.stdin_rid = Some(rid);
-  }
-  if c.stdout().is_some() {
-    let stdout = c.stdout().take().unwrap();
-    let rid = new_rid();
-    let r = tg.insert(rid, Repr::ChildStdout(stdout));
-    assert!(r.is_none());
-    resources.stdout_rid = Some(rid);
-  }
-  if c.stderr().is_some() {
-    let stderr = c.stderr().take().unwrap();
-    let rid = new_rid();
-    let r = tg.insert(rid, Repr::ChildStderr(stderr));
-    assert!(r.is_none());
-    resources.stderr_rid = Some(rid);
+/// Spawns `command` with the given stdio and registers the child like
+/// `add_child()`. If the command can't be spawned, E.G. because the program
+/// doesn't exist or isn't executable, the OS error is reported with the
+/// matching kind (`NotFound`, `PermissionDenied`, ...).
+pub fn spawn(
+  mut command: std::process::Command,
+  stdin: ProcessStdio,
+  stdout: ProcessStdio,
+  stderr: ProcessStdio,
+) -> FutureResult<ChildResources, DenoError> {
+  use tokio_process::CommandExt;
+  command.stdin(process_stdio(stdin));
+  command.stdout(process_stdio(stdout));
+  command.stderr(process_stdio(stderr));
+  let result = command
+    .spawn_async()
+    .map_err(DenoError::from)
+    .and_then(|child| add_child(child, stdin, stdout, stderr));
+  futures::future::result(result)
+}
+
+fn process_stdio(v: ProcessStdio) -> std::process::Stdio {
+  match v {
+    ProcessStdio::Inherit => std::process::Stdio::inherit(),
+    ProcessStdio::Piped => std::process::Stdio::piped(),
+    ProcessStdio::Null => std::process::Stdio::null(),
   }
+}
+
+#[test]
+fn test_spawn_not_found() {
+  let command = std::process::Command::new("deno_test_no_such_binary");
+  let err = spawn(
+    command,
+    ProcessStdio::Null,
+    ProcessStdio::Null,
+    ProcessStdio::Null,
+  )
+  .wait()
+  .unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::NotFound);
+}
 
-  let r = tg.insert(child_rid, Repr::Child(Box::new(c)));
-  assert!(r.is_none());
+#[cfg(unix)]
+#[test]
+fn test_spawn() {
+  tokio_util::init(|| {
+    let mut command = std::process::Command::new("echo");
+    command.arg("hi");
+    let child_resources = tokio_util::block_on(spawn(
+      command,
+      ProcessStdio::Null,
+      ProcessStdio::Piped,
+      ProcessStdio::Inherit,
+    ))
+    .unwrap();
+    assert!(child_resources.pid > 0);
+    let stdout_rid = child_resources.stdout.rid().unwrap();
+    let (_, out) =
+      tokio_util::block_on(poll_read_to_end(Resource { rid: stdout_rid }))
+        .unwrap();
+    assert_eq!(out, b"hi\n");
+    let status =
+      tokio_util::block_on(child_status(child_resources.child_rid).unwrap())
+        .unwrap();
+    assert!(status.success());
+    close(stdout_rid).unwrap();
+    close(child_resources.child_rid).unwrap();
+  });
+}
 
-  resources
+#[cfg(unix)]
+#[test]
+fn test_add_child_stdio_disposition() {
+  use std::process::{Command, Stdio};
+  use tokio_process::CommandExt;
+  tokio_util::init(|| {
+    let child = Command::new("true")
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .spawn_async()
+      .unwrap();
+    let child_resources = add_child(
+      child,
+      ProcessStdio::Null,
+      ProcessStdio::Piped,
+      ProcessStdio::Inherit,
+    )
+    .unwrap();
+    assert_eq!(child_resources.stdin, StdioDisposition::Null);
+    assert_eq!(child_resources.stderr, StdioDisposition::Inherited);
+    let stdout_rid = child_resources.stdout.rid().unwrap();
+    assert_eq!(child_resources.stdout, StdioDisposition::Piped(stdout_rid));
+    assert_eq!(resource_kind(stdout_rid), Some(ResourceKind::ChildStdout));
+    assert!(child_resources.child_rid < stdout_rid);
+    assert_eq!(child_resources.stderr.rid(), None);
+    let status =
+      tokio_util::block_on(child_status(child_resources.child_rid).unwrap())
+        .unwrap();
+    assert!(status.success());
+    close(stdout_rid).unwrap();
+    close(child_resources.child_rid).unwrap();
+  });
 }
 
 pub struct ChildStatus {
@@ -448,7 +4431,13 @@ impl Future for ChildStatus {
     let mut table = RESOURCE_TABLE.lock().unwrap();
     let maybe_repr = table.get_mut(&self.rid);
     match maybe_repr {
-      Some(Repr::Child(ref mut child)) => child.poll().map_err(DenoError::from),
+      Some(Repr::Child(ref mut child, ref waiter)) => {
+        let r = child.poll().map_err(DenoError::from);
+        if let Ok(Async::NotReady) = r {
+          waiter.lock().unwrap().replace(futures::task::current());
+        }
+        r
+      }
       _ => Err(bad_resource()),
     }
   }
@@ -458,11 +4447,297 @@ pub fn child_status(rid: ResourceId) -> DenoResult<ChildStatus> {
   let mut table = RESOURCE_TABLE.lock().unwrap();
   let maybe_repr = table.get_mut(&rid);
   match maybe_repr {
-    Some(Repr::Child(ref mut _child)) => Ok(ChildStatus { rid }),
+    Some(Repr::Child(_, _)) => Ok(ChildStatus { rid }),
+    _ => Err(bad_resource()),
+  }
+}
+
+// Wakes up the task waiting on a child, if any.
+struct ForwardNotify(ChildWaiter);
+
+impl futures::executor::Notify for ForwardNotify {
+  fn notify(&self, _id: usize) {
+    if let Some(task) = self.0.lock().unwrap().as_ref() {
+      task.notify();
+    }
+  }
+}
+
+/// Checks whether a child has exited without waiting for it. Returns None if
+/// it is still running. The child is left in the table, so this can be called
+/// repeatedly, and `child_status()` can still be used afterwards.
+pub fn try_child_status(rid: ResourceId) -> DenoResult<Option<ExitStatus>> {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  match table.get_mut(&rid) {
+    Some(Repr::Child(ref mut child, ref waiter)) => {
+      // Polling the child registers interest in its exit with the polling
+      // task, replacing the task waiting in child_status(). Poll it from a
+      // task of its own, which passes the wakeup on to that waiter.
+      let notify = ForwardNotify(waiter.clone());
+      let notify = futures::executor::NotifyHandle::from(Arc::new(notify));
+      let poll =
+        futures::executor::spawn(futures::future::poll_fn(|| child.poll()))
+          .poll_future_notify(&notify, 0);
+      match poll {
+        Ok(Async::Ready(status)) => Ok(Some(status)),
+        Ok(Async::NotReady) => Ok(None),
+        Err(err) => Err(DenoError::from(err)),
+      }
+    }
+    _ => Err(bad_resource()),
+  }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_try_child_status() {
+  use std::process::Command;
+  use tokio_process::CommandExt;
+  tokio_util::init(|| {
+    let child = Command::new("sleep").arg("1").spawn_async().unwrap();
+    let rid = add_child(
+      child,
+      ProcessStdio::Inherit,
+      ProcessStdio::Inherit,
+      ProcessStdio::Inherit,
+    )
+    .unwrap()
+    .child_rid;
+    assert_eq!(try_child_status(rid).unwrap(), None);
+    let mut status = None;
+    for _ in 0..50 {
+      std::thread::sleep(Duration::from_millis(100));
+      status = try_child_status(rid).unwrap();
+      if status.is_some() {
+        break;
+      }
+    }
+    assert!(status.unwrap().success());
+    // The child is still there and reports the same status.
+    assert_eq!(try_child_status(rid).unwrap(), status);
+    let status = tokio_util::block_on(child_status(rid).unwrap()).unwrap();
+    assert!(status.success());
+    close(rid).unwrap();
+    assert_eq!(
+      try_child_status(rid).unwrap_err().kind(),
+      errors::ErrorKind::BadResource
+    );
+  });
+}
+
+#[cfg(unix)]
+#[test]
+fn test_try_child_status_keeps_waiter() {
+  use std::process::Command;
+  use std::sync::mpsc;
+  use tokio_process::CommandExt;
+  tokio_util::init(|| {
+    let child = Command::new("sleep").arg("1").spawn_async().unwrap();
+    let rid = add_child(
+      child,
+      ProcessStdio::Inherit,
+      ProcessStdio::Inherit,
+      ProcessStdio::Inherit,
+    )
+    .unwrap()
+    .child_rid;
+    let (tx, rx) = mpsc::channel();
+    tokio::spawn(child_status(rid).unwrap().then(move |r| {
+      tx.send(r.map_err(|e| e.to_string())).unwrap();
+      Ok(())
+    }));
+    // Let the waiter poll the child first, then poll it from here while it
+    // is still running.
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(try_child_status(rid).unwrap(), None);
+    // The waiter still gets woken up when the child exits.
+    let status = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+    assert!(status.success());
+    close(rid).unwrap();
+  });
+}
+
+/// Like `child_status()`, but gives up waiting after `dur`. Resolves to None
+/// if the child is still running by then. The child resource is left in the
+/// table either way, so it can be waited on again.
+pub fn child_status_timeout(
+  rid: ResourceId,
+  dur: Duration,
+) -> DenoResult<
+  Box<dyn Future<Item = Option<ExitStatus>, Error = DenoError> + Send>,
+> {
+  let status = child_status(rid)?;
+  let timeout = tokio::timer::Timeout::new(status, dur).then(|r| match r {
+    Ok(exit_status) => Ok(Some(exit_status)),
+    Err(ref err) if err.is_elapsed() => Ok(None),
+    Err(err) => match err.into_inner() {
+      Some(err) => Err(err),
+      None => Err(errors::new(
+        errors::ErrorKind::Other,
+        String::from("timer error while waiting for child"),
+      )),
+    },
+  });
+  Ok(Box::new(timeout))
+}
+
+#[cfg(unix)]
+#[test]
+fn test_child_status_timeout() {
+  use std::process::Command;
+  use tokio_process::CommandExt;
+  tokio_util::init(|| {
+    let child = Command::new("sleep").arg("5").spawn_async().unwrap();
+    let rid = add_child(
+      child,
+      ProcessStdio::Inherit,
+      ProcessStdio::Inherit,
+      ProcessStdio::Inherit,
+    )
+    .unwrap()
+    .child_rid;
+    let timeout =
+      child_status_timeout(rid, Duration::from_millis(100)).unwrap();
+    assert_eq!(tokio_util::block_on(timeout).unwrap(), None);
+    // The child can still be waited on after the timeout.
+    child_kill(rid, libc::SIGKILL).unwrap();
+    let timeout = child_status_timeout(rid, Duration::from_secs(5)).unwrap();
+    let status = tokio_util::block_on(timeout).unwrap().unwrap();
+    assert!(!status.success());
+    close(rid).unwrap();
+  });
+}
+
+/// Returns the OS process id of a child resource.
+pub fn child_pid(rid: ResourceId) -> DenoResult<u32> {
+  let table = RESOURCE_TABLE.lock().unwrap();
+  match table.get(&rid) {
+    Some(Repr::Child(ref child, _)) => Ok(child.id()),
+    _ => Err(bad_resource()),
+  }
+}
+
+/// Sends `signal` to a child resource. The child stays in the table, so that
+/// `child_status()` can still be used to observe its exit.
+#[cfg(unix)]
+pub fn child_kill(rid: ResourceId, signal: i32) -> DenoResult<()> {
+  let table = RESOURCE_TABLE.lock().unwrap();
+  match table.get(&rid) {
+    Some(Repr::Child(ref child, _)) => {
+      let r = unsafe { libc::kill(child.id() as libc::pid_t, signal) };
+      if r == 0 {
+        Ok(())
+      } else {
+        Err(DenoError::from(std::io::Error::last_os_error()))
+      }
+    }
+    _ => Err(bad_resource()),
+  }
+}
+
+/// There are no signals on Windows, so `signal` is ignored and the child is
+/// always terminated.
+#[cfg(not(unix))]
+pub fn child_kill(rid: ResourceId, _signal: i32) -> DenoResult<()> {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  match table.get_mut(&rid) {
+    Some(Repr::Child(ref mut child, _)) => {
+      child.kill().map_err(DenoError::from)
+    }
     _ => Err(bad_resource()),
   }
 }
 
+#[cfg(unix)]
+#[test]
+fn test_child_kill() {
+  use std::os::unix::process::ExitStatusExt;
+  use std::process::Command;
+  use tokio_process::CommandExt;
+  tokio_util::init(|| {
+    let child = Command::new("sleep").arg("10").spawn_async().unwrap();
+    let pid = child.id();
+    let child_resources = add_child(
+      child,
+      ProcessStdio::Inherit,
+      ProcessStdio::Inherit,
+      ProcessStdio::Inherit,
+    )
+    .unwrap();
+    let rid = child_resources.child_rid;
+    assert_eq!(child_pid(rid).unwrap(), pid);
+    child_kill(rid, libc::SIGKILL).unwrap();
+    let status = tokio_util::block_on(child_status(rid).unwrap()).unwrap();
+    assert!(!status.success());
+    assert_eq!(status.signal(), Some(libc::SIGKILL));
+    close(rid).unwrap();
+  });
+}
+
+/// Closes the stdin pipe of a child, so that the child reads EOF, without
+/// touching the child resource itself. If `rid` was dup'd, the pipe is only
+/// closed once every rid referring to it is.
+pub fn close_child_stdin(rid: ResourceId) -> DenoResult<()> {
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  match table.get(&rid) {
+    Some(Repr::ChildStdin(_)) => {}
+    _ => return Err(bad_resource()),
+  }
+  table.remove(&rid);
+  Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_close_child_stdin() {
+  use std::process::{Command, Stdio};
+  use tokio_process::CommandExt;
+  tokio_util::init(|| {
+    let child = Command::new("cat")
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .spawn_async()
+      .unwrap();
+    let child_resources = add_child(
+      child,
+      ProcessStdio::Piped,
+      ProcessStdio::Piped,
+      ProcessStdio::Inherit,
+    )
+    .unwrap();
+    let stdin_rid = child_resources.stdin.rid().unwrap();
+    let stdout = Resource {
+      rid: child_resources.stdout.rid().unwrap(),
+    };
+
+    let stdin = Resource { rid: stdin_rid };
+    tokio_util::block_on(tokio::io::write_all(stdin, b"hello\n")).unwrap();
+    close_child_stdin(stdin_rid).unwrap();
+    assert!(get_type(stdin_rid).is_none());
+
+    // cat only exits once it has seen EOF on its stdin.
+    let (_, output) =
+      tokio_util::block_on(poll_read_to_end(stdout.clone())).unwrap();
+    assert_eq!(output, b"hello\n");
+    let status =
+      tokio_util::block_on(child_status(child_resources.child_rid).unwrap())
+        .unwrap();
+    assert!(status.success());
+    stdout.close();
+    close(child_resources.child_rid).unwrap();
+  });
+}
+
+#[test]
+fn test_child_bad_resource() {
+  let err = child_pid(0).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let err = child_kill(0, 9).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  let err = close_child_stdin(0).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
 pub fn get_repl(rid: ResourceId) -> DenoResult<Arc<Mutex<Repl>>> {
   let mut table = RESOURCE_TABLE.lock().unwrap();
   let maybe_repr = table.get_mut(&rid);
@@ -472,10 +4747,202 @@ pub fn get_repl(rid: ResourceId) -> DenoResult<Arc<Mutex<Repl>>> {
   }
 }
 
+/// Creates a shared buffer resource of `size` zeroed bytes.
+pub fn add_shared_buf(size: usize) -> DenoResult<Resource> {
+  add_shared_buf_from(Arc::new(Mutex::new(vec![0; size])))
+}
+
+/// Registers an existing shared buffer, E.G. one obtained from another
+/// isolate with `get_shared_buf()`, under a new rid.
+pub fn add_shared_buf_from(buf: SharedBuf) -> DenoResult<Resource> {
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::SharedBuf(buf))?;
+  Ok(Resource { rid })
+}
+
+pub fn get_shared_buf(rid: ResourceId) -> DenoResult<SharedBuf> {
+  let table = RESOURCE_TABLE.lock().unwrap();
+  match table.get(&rid) {
+    Some(Repr::SharedBuf(buf)) => Ok(buf.clone()),
+    _ => Err(bad_resource()),
+  }
+}
+
+fn shared_buf_out_of_range() -> DenoError {
+  errors::new(
+    errors::ErrorKind::InvalidInput,
+    String::from("offset is out of the shared buffer's bounds"),
+  )
+}
+
+#[test]
+fn test_shared_buf() {
+  let a = add_shared_buf(16).unwrap();
+  assert_eq!(get_type(a.rid), Some(String::from("sharedBuf")));
+  let b = add_shared_buf_from(get_shared_buf(a.rid).unwrap()).unwrap();
+  assert_ne!(a.rid, b.rid);
+
+  a.write_shared_buf(4, b"hello").unwrap();
+  let mut buf = [0u8; 8];
+  assert_eq!(b.read_shared_buf(4, &mut buf).unwrap(), 8);
+  assert_eq!(&buf, b"hello\0\0\0");
+  assert_eq!(b.read_shared_buf(12, &mut buf).unwrap(), 4);
+
+  let err = b.write_shared_buf(12, b"hello").unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::InvalidInput);
+  let err = a.read_shared_buf(17, &mut buf).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::InvalidInput);
+
+  // The memory outlives either rid.
+  a.close();
+  assert_eq!(b.read_shared_buf(4, &mut buf[..5]).unwrap(), 5);
+  assert_eq!(&buf[..5], b"hello");
+  b.close();
+  assert!(get_shared_buf(b.rid).is_err());
+}
+
+/// Creates a resource that yields the environment variables of the process,
+/// one per `Resource::poll_next_env()`. The variables are snapshotted now, so
+/// later changes to the environment are not seen. Variables that aren't valid
+/// unicode are skipped.
+pub fn add_env_iter() -> DenoResult<Resource> {
+  let vars: Vec<(String, String)> = std::env::vars_os()
+    .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+    .collect();
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::EnvIter(vars.into_iter()))?;
+  Ok(Resource { rid })
+}
+
+#[test]
+fn test_env_iter() {
+  std::env::set_var("DENO_TEST_ENV_ITER", "hello");
+  let mut resource = add_env_iter().unwrap();
+  assert_eq!(get_type(resource.rid), Some(String::from("envIter")));
+  // Not part of the snapshot.
+  std::env::set_var("DENO_TEST_ENV_ITER_LATE", "late");
+  let vars: Vec<(String, String)> =
+    futures::stream::poll_fn(|| resource.poll_next_env())
+      .collect()
+      .wait()
+      .unwrap();
+  assert!(
+    vars.contains(&("DENO_TEST_ENV_ITER".to_string(), "hello".to_string()))
+  );
+  assert!(!vars.iter().any(|(k, _)| k == "DENO_TEST_ENV_ITER_LATE"));
+  // The iterator stays exhausted.
+  assert_eq!(resource.poll_next_env().unwrap(), Async::Ready(None));
+  resource.close();
+  assert!(resource.poll_next_env().is_err());
+}
+
+/// Creates a resource that reads `inner_rid` line by line, see
+/// `Resource::poll_next_line()`. The line reader takes over `inner_rid`:
+/// closing it closes `inner_rid` too.
+pub fn add_line_reader(inner_rid: ResourceId) -> DenoResult<Resource> {
+  match resource_kind(inner_rid) {
+    Some(kind) if kind.capabilities().readable => {}
+    _ => return Err(bad_resource()),
+  }
+  let reader =
+    std::io::BufReader::new(PolledResource(Resource { rid: inner_rid }));
+  let lines = Arc::new(Mutex::new(tokio::io::lines(reader)));
+  let mut tg = RESOURCE_TABLE.lock().unwrap();
+  let rid = insert(&mut tg, Repr::LineReader(inner_rid, lines))?;
+  Ok(Resource { rid })
+}
+
+#[test]
+fn test_line_reader() {
+  let mut file = tempfile::tempfile().unwrap();
+  file.write_all(b"one\ntwo\r\nthree").unwrap();
+  file.seek(SeekFrom::Start(0)).unwrap();
+  let file = add_fs_file(tokio::fs::File::from_std(file)).unwrap();
+  let mut reader = add_line_reader(file.rid).unwrap();
+  assert_eq!(get_type(reader.rid), Some(String::from("lineReader")));
+
+  tokio_util::init(|| {
+    let mut r = reader.clone();
+    let lines = tokio_util::block_on(
+      futures::stream::poll_fn(move || r.poll_next_line()).collect(),
+    )
+    .unwrap();
+    assert_eq!(lines, vec!["one", "two", "three"]);
+    let mut r = reader.clone();
+    let next = tokio_util::block_on(futures::future::poll_fn(move || {
+      r.poll_next_line()
+    })).unwrap();
+    assert_eq!(next, None);
+  });
+
+  reader.close();
+  assert!(lookup(file.rid).is_none());
+  assert!(reader.poll_next_line().is_err());
+
+  let (pipe_reader, pipe_writer) = new_pipe().unwrap();
+  let err = add_line_reader(pipe_writer.rid).unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+  pipe_reader.close();
+  pipe_writer.close();
+}
+
 pub fn lookup(rid: ResourceId) -> Option<Resource> {
   debug!("resource lookup {}", rid);
-  let table = RESOURCE_TABLE.lock().unwrap();
-  table.get(&rid).map(|_| Resource { rid })
+  resource_kind(rid).map(|_| Resource { rid })
+}
+
+#[test]
+fn test_concurrent_lookup() {
+  let file =
+    add_fs_file(tokio::fs::File::from_std(tempfile::tempfile().unwrap()))
+      .unwrap();
+  let rid = file.rid;
+  let threads: Vec<_> = (0..8)
+    .map(|_| {
+      std::thread::spawn(move || {
+        for _ in 0..1000 {
+          assert_eq!(lookup(rid).unwrap().rid, rid);
+          assert_eq!(resource_kind(0), Some(ResourceKind::Stdin));
+          assert_eq!(get_type(rid), Some(String::from("fsFile")));
+        }
+      })
+    }).collect();
+  // Meanwhile other resources come and go.
+  for _ in 0..100 {
+    let (reader, writer) = new_pipe().unwrap();
+    let dup_rid = dup(reader.rid).unwrap();
+    assert_eq!(resource_kind(dup_rid), Some(ResourceKind::MemPipeReader));
+    reader.close();
+    writer.close();
+    close(dup_rid).unwrap();
+    assert!(lookup(dup_rid).is_none());
+  }
+  for t in threads {
+    t.join().unwrap();
+  }
+  file.close();
+  assert!(lookup(rid).is_none());
+}
+
+#[test]
+fn test_kind_index() {
+  let mut table = ResourceTable::new();
+  let (reader, writer) = mem_pipe::pipe(1);
+  let reader_rid = insert(&mut table, Repr::MemPipeReader(reader)).unwrap();
+  let writer_rid = insert(&mut table, Repr::MemPipeWriter(writer)).unwrap();
+  let dup_rid = new_rid().unwrap();
+  table.dup(reader_rid, dup_rid).unwrap();
+  table.swap_repr(&dup_rid, &writer_rid).unwrap();
+  {
+    let kinds = table.kinds.read().unwrap();
+    assert_eq!(kinds[&reader_rid], ResourceKind::MemPipeWriter);
+    assert_eq!(kinds[&dup_rid], ResourceKind::MemPipeWriter);
+    assert_eq!(kinds[&writer_rid], ResourceKind::MemPipeReader);
+  }
+  table.remove(&reader_rid);
+  let kinds = table.kinds.read().unwrap();
+  assert_eq!(kinds.len(), 2);
+  assert!(!kinds.contains_key(&reader_rid));
 }
 
 pub type EagerRead<R, T> =
@@ -484,26 +4951,88 @@ pub type EagerRead<R, T> =
 pub type EagerWrite<R, T> =
   Either<tokio_write::Write<R, T>, FutureResult<(R, T, usize), std::io::Error>>;
 
-pub type EagerAccept = Either<
-  tokio_util::Accept,
-  FutureResult<(tokio::net::TcpStream, std::net::SocketAddr), std::io::Error>,
->;
+// Bytes moved by the eager fast paths never go through poll_read() or
+// poll_write(), so they are added to the resource's counter here. A
+// FutureResult is always ready, so it is fine to poll it outside of a task.
+fn count_eager<A, T>(
+  counter: Option<&AtomicUsize>,
+  f: Either<A, FutureResult<(Resource, T, usize), std::io::Error>>,
+) -> Either<A, FutureResult<(Resource, T, usize), std::io::Error>> {
+  match (counter, f) {
+    (Some(counter), Either::B(mut result)) => {
+      Either::B(futures::future::result(match result.poll() {
+        Ok(Async::Ready((resource, buf, n))) => {
+          counter.fetch_add(n, Ordering::SeqCst);
+          Ok((resource, buf, n))
+        }
+        Ok(Async::NotReady) => unreachable!(),
+        Err(err) => Err(err),
+      }))
+    }
+    (_, f) => f,
+  }
+}
+
+pub type EagerAccept =
+  Either<tokio_util::Accept, FutureResult<Accepted, std::io::Error>>;
 
-#[cfg(not(unix))]
-#[allow(unused_mut)]
+// Like the unix versions below, attempt the read() or write() on the main
+// thread. Only TCP sockets take the fast path on Windows.
+#[cfg(windows)]
 pub fn eager_read<T: AsMut<[u8]>>(
   resource: Resource,
-  mut buf: T,
+  buf: T,
 ) -> EagerRead<Resource, T> {
-  Either::A(tokio_io::io::read(resource, buf))
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  let rid = resource.rid;
+  let maybe_repr = table.get_mut(&rid);
+  let result = match maybe_repr {
+    None => Either::B(futures::future::err(bad_rid_io_error())),
+    Some(repr) => match repr {
+      Repr::TcpStream(ref mut tcp_stream) => {
+        eager::tcp_read(tcp_stream, resource, buf)
+      }
+      _ => Either::A(tokio_io::io::read(resource, buf)),
+    },
+  };
+  count_eager(table.entry(&rid).map(|e| &e.bytes_read), result)
 }
 
-#[cfg(not(unix))]
+#[cfg(windows)]
 pub fn eager_write<T: AsRef<[u8]>>(
   resource: Resource,
   buf: T,
 ) -> EagerWrite<Resource, T> {
-  Either::A(tokio_write::write(resource, buf))
+  let mut table = RESOURCE_TABLE.lock().unwrap();
+  let rid = resource.rid;
+  let maybe_repr = table.get_mut(&rid);
+  let result = match maybe_repr {
+    None => Either::B(futures::future::err(bad_rid_io_error())),
+    Some(repr) => match repr {
+      Repr::TcpStream(ref mut tcp_stream) => {
+        eager::tcp_write(tcp_stream, resource, buf)
+      }
+      _ => Either::A(tokio_write::write(resource, buf)),
+    },
+  };
+  count_eager(table.entry(&rid).map(|e| &e.bytes_written), result)
+}
+
+#[cfg(windows)]
+#[test]
+fn test_eager_read_tcp_stream_windows() {
+  tokio_util::init(|| {
+    let (client, server) = tcp_loopback_pair();
+    let client = add_tcp_stream(client).unwrap();
+    let server = add_tcp_stream(server).unwrap();
+    tokio_util::block_on(eager_write(client.clone(), b"hello")).unwrap();
+    let (_, buf, nread) =
+      tokio_util::block_on(eager_read(server.clone(), vec![0; 5])).unwrap();
+    assert_eq!(nread, 5);
+    assert_eq!(&buf[..], b"hello");
+    client.close();
+    server.close();
+  });
 }
 
 #[cfg(not(unix))]
@@ -519,16 +5048,60 @@ pub fn eager_read<T: AsMut<[u8]>>(
   buf: T,
 ) -> EagerRead<Resource, T> {
   let mut table = RESOURCE_TABLE.lock().unwrap();
-  let maybe_repr = table.get_mut(&resource.rid);
-  match maybe_repr {
-    None => panic!("bad rid"),
+  let rid = resource.rid;
+  let maybe_repr = table.get_mut(&rid);
+  let result = match maybe_repr {
+    None => Either::B(futures::future::err(bad_rid_io_error())),
     Some(repr) => match repr {
       Repr::TcpStream(ref mut tcp_stream) => {
         eager::tcp_read(tcp_stream, resource, buf)
       }
+      Repr::ChildStdout(ref child_stdout) => {
+        eager::child_read(child_stdout, resource, buf)
+      }
+      Repr::ChildStderr(ref child_stderr) => {
+        eager::child_read(child_stderr, resource, buf)
+      }
       _ => Either::A(tokio_io::io::read(resource, buf)),
     },
-  }
+  };
+  count_eager(table.entry(&rid).map(|e| &e.bytes_read), result)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_eager_read_child_stdout() {
+  use std::process::{Command, Stdio};
+  use tokio_process::CommandExt;
+  tokio_util::init(|| {
+    let child = Command::new("echo")
+      .arg("hello")
+      .stdout(Stdio::piped())
+      .spawn_async()
+      .unwrap();
+    let child_resources = add_child(
+      child,
+      ProcessStdio::Inherit,
+      ProcessStdio::Piped,
+      ProcessStdio::Inherit,
+    )
+    .unwrap();
+    let stdout = lookup(child_resources.stdout.rid().unwrap()).unwrap();
+    let mut output = Vec::new();
+    loop {
+      let (_, buf, nread) =
+        tokio_util::block_on(eager_read(stdout.clone(), vec![0; 64])).unwrap();
+      if nread == 0 {
+        break;
+      }
+      output.extend_from_slice(&buf[..nread]);
+    }
+    assert_eq!(output, b"hello\n");
+    let status = child_status(child_resources.child_rid).unwrap();
+    assert!(tokio_util::block_on(status).unwrap().success());
+    stdout.close();
+    close(child_resources.child_rid).unwrap();
+  });
 }
 
 // This is an optimization that Tokio should do.
@@ -539,16 +5112,54 @@ pub fn eager_write<T: AsRef<[u8]>>(
   buf: T,
 ) -> EagerWrite<Resource, T> {
   let mut table = RESOURCE_TABLE.lock().unwrap();
-  let maybe_repr = table.get_mut(&resource.rid);
-  match maybe_repr {
-    None => panic!("bad rid"),
+  let rid = resource.rid;
+  if let Some(Repr::FsFile(_)) = table.get(&rid) {
+    // This write blocks, see eager::fs_write().
+    // tokio::fs::File does not expose its fd. Take it out of the table for
+    // the duration of the write and put it back while still holding the lock.
+    let result = table
+      .map_repr(&rid, |repr| match repr {
+        Repr::FsFile(fs_file) => {
+          let std_file = fs_file.into_std();
+          let result = eager::fs_write(&std_file, resource, buf);
+          (Repr::FsFile(tokio::fs::File::from_std(std_file)), result)
+        }
+        _ => unreachable!(),
+      }).unwrap();
+    return count_eager(table.entry(&rid).map(|e| &e.bytes_written), result);
+  }
+  let maybe_repr = table.get_mut(&rid);
+  let result = match maybe_repr {
+    None => Either::B(futures::future::err(bad_rid_io_error())),
     Some(repr) => match repr {
       Repr::TcpStream(ref mut tcp_stream) => {
         eager::tcp_write(tcp_stream, resource, buf)
       }
       _ => Either::A(tokio_write::write(resource, buf)),
     },
-  }
+  };
+  count_eager(table.entry(&rid).map(|e| &e.bytes_written), result)
+}
+
+#[cfg(unix)]
+#[test]
+fn test_eager_write_fs_file() {
+  let dir = tempfile::TempDir::new().unwrap();
+  let path = dir.path().join("eager_write.txt");
+  let std_file = std::fs::File::create(&path).unwrap();
+  let resource = add_fs_file(tokio::fs::File::from_std(std_file)).unwrap();
+  let mut expected = Vec::new();
+  tokio_util::init(|| {
+    for i in 0..1000 {
+      let chunk = format!("{},", i).into_bytes();
+      expected.extend_from_slice(&chunk);
+      let (_, _, nwritten) =
+        tokio_util::block_on(eager_write(resource.clone(), chunk)).unwrap();
+      assert!(nwritten > 0);
+    }
+  });
+  resource.close();
+  assert_eq!(std::fs::read(&path).unwrap(), expected);
 }
 
 #[cfg(unix)]
@@ -556,7 +5167,7 @@ pub fn eager_accept(resource: Resource) -> EagerAccept {
   let mut table = RESOURCE_TABLE.lock().unwrap();
   let maybe_repr = table.get_mut(&resource.rid);
   match maybe_repr {
-    None => panic!("bad rid"),
+    None => Either::B(futures::future::err(bad_rid_io_error())),
     Some(repr) => match repr {
       Repr::TcpListener(ref mut tcp_listener, _) => {
         eager::tcp_accept(tcp_listener, resource)
@@ -566,6 +5177,218 @@ pub fn eager_accept(resource: Resource) -> EagerAccept {
   }
 }
 
+const COPY_BUF_SIZE: usize = 64 * 1024;
+
+// The part of a buffer that is still to be written (or filled).
+struct Window<B> {
+  buf: B,
+  start: usize,
+  end: usize,
+}
+
+impl<B: AsRef<[u8]>> AsRef<[u8]> for Window<B> {
+  fn as_ref(&self) -> &[u8] {
+    &self.buf.as_ref()[self.start..self.end]
+  }
+}
+
+impl<B: AsMut<[u8]>> AsMut<[u8]> for Window<B> {
+  fn as_mut(&mut self) -> &mut [u8] {
+    &mut self.buf.as_mut()[self.start..self.end]
+  }
+}
+
+/// Copies everything from `src_rid` to `dst_rid` until EOF on the source and
+/// resolves to the number of bytes copied. A chunk is only read once the
+/// previous one has been written completely, so a slow destination holds back
+/// the source. Reads and writes go through `eager_read()` and
+/// `eager_write()`. Neither resource is closed.
+pub fn copy(
+  src_rid: ResourceId,
+  dst_rid: ResourceId,
+) -> impl Future<Item = u64, Error = DenoError> {
+  if lookup(src_rid).is_none() || lookup(dst_rid).is_none() {
+    return Either::A(futures::future::err(bad_resource()));
+  }
+  let src = Resource { rid: src_rid };
+  let dst = Resource { rid: dst_rid };
+  let copy = futures::future::loop_fn(
+    (vec![0; COPY_BUF_SIZE], 0u64),
+    move |(buf, total)| {
+      let dst = dst.clone();
+      eager_read(src.clone(), buf).and_then(move |(_, buf, nread)| {
+        if nread == 0 {
+          return Either::A(futures::future::ok(Loop::Break(total)));
+        }
+        let write = write_all_eager(dst, buf, nread)
+          .map(move |buf| Loop::Continue((buf, total + nread as u64)));
+        Either::B(write)
+      })
+    },
+  )
+  .map_err(DenoError::from);
+  Either::B(copy)
+}
+
+// Writes `buf[..len]` to `dst` and hands the buffer back.
+fn write_all_eager<B: AsRef<[u8]>>(
+  dst: Resource,
+  buf: B,
+  len: usize,
+) -> impl Future<Item = B, Error = std::io::Error> {
+  futures::future::loop_fn((buf, 0), move |(buf, start)| {
+    let window = Window {
+      buf,
+      start,
+      end: len,
+    };
+    eager_write(dst.clone(), window).and_then(|(_, window, nwritten)| {
+      if nwritten == 0 {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::WriteZero,
+          "failed to write the whole buffer",
+        ));
+      }
+      let start = window.start + nwritten;
+      if start == window.end {
+        Ok(Loop::Break(window.buf))
+      } else {
+        Ok(Loop::Continue((window.buf, start)))
+      }
+    })
+  })
+}
+
+#[test]
+fn test_copy() {
+  let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+  let mut file = tempfile::tempfile().unwrap();
+  file.write_all(&data).unwrap();
+  file.seek(SeekFrom::Start(0)).unwrap();
+  let file = add_fs_file(tokio::fs::File::from_std(file)).unwrap();
+  let (reader, writer) = new_pipe().unwrap();
+
+  tokio_util::init(|| {
+    let (tx, rx) = futures::sync::oneshot::channel();
+    let writer_rid = writer.rid;
+    tokio::spawn(copy(file.rid, writer_rid).then(move |r| {
+      // Closing the writer lets the reader see EOF.
+      writer.close();
+      tx.send(r.map_err(|e| e.to_string())).unwrap();
+      Ok(())
+    }));
+    let (reader, received) =
+      tokio_util::block_on(poll_read_to_end(reader)).unwrap();
+    assert_eq!(rx.wait().unwrap().unwrap(), data.len() as u64);
+    assert_eq!(received, data);
+    reader.close();
+    file.close();
+  });
+
+  let err = copy(9999, 1).wait().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+/// Writes all of `buf` to `rid`, looping over short writes. Each write goes
+/// through `eager_write()`, so a full resource holds the future back until it
+/// has room again.
+pub fn write_all<T: AsRef<[u8]>>(
+  rid: ResourceId,
+  buf: T,
+) -> impl Future<Item = (), Error = DenoError> {
+  if lookup(rid).is_none() {
+    return Either::A(futures::future::err(bad_resource()));
+  }
+  let len = buf.as_ref().len();
+  if len == 0 {
+    return Either::A(futures::future::ok(()));
+  }
+  let write = write_all_eager(Resource { rid }, buf, len)
+    .map(|_| ())
+    .map_err(DenoError::from);
+  Either::B(write)
+}
+
+#[test]
+fn test_write_all() {
+  let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+  // The pipe only buffers PIPE_CAPACITY writes, so the writer has to wait
+  // for the reader repeatedly.
+  let (reader, writer) = new_pipe().unwrap();
+
+  tokio_util::init(|| {
+    let (tx, rx) = futures::sync::oneshot::channel();
+    tokio::spawn(write_all(writer.rid, data.clone()).then(move |r| {
+      writer.close();
+      tx.send(r.map_err(|e| e.to_string())).unwrap();
+      Ok(())
+    }));
+    let (reader, received) =
+      tokio_util::block_on(poll_read_to_end(reader)).unwrap();
+    rx.wait().unwrap().unwrap();
+    assert_eq!(received, data);
+    reader.close();
+  });
+
+  let err = write_all(9999, b"x").wait().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
+/// Reads exactly `n` bytes from `rid`, looping over short reads. Fails with
+/// `UnexpectedEof` if the resource reaches EOF first.
+pub fn read_exact(
+  rid: ResourceId,
+  n: usize,
+) -> impl Future<Item = Vec<u8>, Error = DenoError> {
+  if lookup(rid).is_none() {
+    return Either::A(futures::future::err(bad_resource()));
+  }
+  if n == 0 {
+    return Either::A(futures::future::ok(Vec::new()));
+  }
+  let resource = Resource { rid };
+  let read = futures::future::loop_fn((vec![0; n], 0), move |(buf, start)| {
+    let window = Window { buf, start, end: n };
+    eager_read(resource.clone(), window).and_then(|(_, window, nread)| {
+      if nread == 0 {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::UnexpectedEof,
+          "resource closed before all bytes were read",
+        ));
+      }
+      let start = window.start + nread;
+      if start == window.end {
+        Ok(Loop::Break(window.buf))
+      } else {
+        Ok(Loop::Continue((window.buf, start)))
+      }
+    })
+  }).map_err(DenoError::from);
+  Either::B(read)
+}
+
+#[test]
+fn test_read_exact() {
+  let (a, mut b) = tcp_loopback_pair();
+  let resource = add_tcp_stream(a).unwrap();
+  b.write_all(b"0123456789").unwrap();
+  b.shutdown(Shutdown::Write).unwrap();
+
+  tokio_util::init(|| {
+    let rid = resource.rid;
+    let head = tokio_util::block_on(read_exact(rid, 4)).unwrap();
+    assert_eq!(head, b"0123");
+    let rest = tokio_util::block_on(read_exact(rid, 6)).unwrap();
+    assert_eq!(rest, b"456789");
+    let err = tokio_util::block_on(read_exact(rid, 1)).unwrap_err();
+    assert_eq!(err.kind(), errors::ErrorKind::UnexpectedEof);
+  });
+  resource.close();
+
+  let err = read_exact(9999, 4).wait().unwrap_err();
+  assert_eq!(err.kind(), errors::ErrorKind::BadResource);
+}
+
 // TODO(kevinkassimo): revamp this after the following lands:
 // https://github.com/tokio-rs/tokio/pull/785
 pub fn seek(
@@ -574,12 +5397,9 @@ pub fn seek(
   whence: u32,
 ) -> Box<dyn Future<Item = (), Error = DenoError> + Send> {
   let mut table = RESOURCE_TABLE.lock().unwrap();
-  // We take ownership of File here.
-  // It is put back below while still holding the lock.
-  let maybe_repr = table.remove(&resource.rid);
-  match maybe_repr {
-    None => panic!("bad rid"),
-    Some(Repr::FsFile(f)) => {
+  match table.get(&resource.rid) {
+    None => Box::new(futures::future::err(bad_resource())),
+    Some(Repr::FsFile(_)) => {
       let seek_from = match whence {
         0 => SeekFrom::Start(offset as u64),
         1 => SeekFrom::Current(offset as i64),
@@ -591,21 +5411,29 @@ pub fn seek(
           )));
         }
       };
-      // Trait Clone not implemented on tokio::fs::File,
-      // so convert to std File first.
-      let std_file = f.into_std();
-      // Create a copy and immediately put back.
-      // We don't want to block other resource ops.
-      // try_clone() would yield a copy containing the same
-      // underlying fd, so operations on the copy would also
-      // affect the one in resource table, and we don't need
-      // to write back.
-      let maybe_std_file_copy = std_file.try_clone();
-      // Insert the entry back with the same rid.
-      table.insert(
-        resource.rid,
-        Repr::FsFile(tokio_fs::File::from_std(std_file)),
-      );
+      // We take ownership of File here.
+      // It is put back below while still holding the lock.
+      let maybe_std_file_copy = table
+        .map_repr(&resource.rid, |repr| match repr {
+          Repr::FsFile(f) => {
+            // Trait Clone not implemented on tokio::fs::File,
+            // so convert to std File first.
+            let std_file = f.into_std();
+            // Create a copy and immediately put back.
+            // We don't want to block other resource ops.
+            // try_clone() would yield a copy containing the same
+            // underlying fd, so operations on the copy would also
+            // affect the one in resource table, and we don't need
+            // to write back.
+            let maybe_std_file_copy = std_file.try_clone();
+            // Insert the entry back with the same rid.
+            (
+              Repr::FsFile(tokio_fs::File::from_std(std_file)),
+              maybe_std_file_copy,
+            )
+          }
+          _ => unreachable!(),
+        }).unwrap();
       if maybe_std_file_copy.is_err() {
         return Box::new(futures::future::err(DenoError::from(
           maybe_std_file_copy.unwrap_err(),
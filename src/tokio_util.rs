@@ -1,4 +1,5 @@
 // Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
+use crate::resources::Accepted;
 use crate::resources::Resource;
 
 use futures;
@@ -6,9 +7,7 @@ use futures::Future;
 use futures::Poll;
 use std::io;
 use std::mem;
-use std::net::SocketAddr;
 use tokio;
-use tokio::net::TcpStream;
 use tokio_executor;
 
 pub fn block_on<F, R, E>(future: F) -> Result<R, E>
@@ -58,11 +57,11 @@ pub struct Accept {
 }
 
 impl Future for Accept {
-  type Item = (TcpStream, SocketAddr);
+  type Item = Accepted;
   type Error = io::Error;
 
   fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-    let (stream, addr) = match self.state {
+    let accepted = match self.state {
       // Similar to try_ready!, but also track/untrack accept task
       // in TcpListener resource.
       // In this way, when the listener is closed, the task can be
@@ -90,7 +89,7 @@ impl Future for Accept {
     };
 
     match mem::replace(&mut self.state, AcceptState::Empty) {
-      AcceptState::Pending(_) => Ok((stream, addr).into()),
+      AcceptState::Pending(_) => Ok(accepted.into()),
       AcceptState::Empty => panic!("invalid internal state"),
     }
   }
@@ -1,12 +1,118 @@
 // Copyright 2018-2019 the Deno authors. All rights reserved. MIT license.
 use crate::libdeno;
 
+use libc::c_char;
 use std::ffi::CStr;
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 pub const DENO: &str = env!("CARGO_PKG_VERSION");
+/// The commit deno was built from, or "unknown" outside of a git checkout.
+pub const GIT_COMMIT: &str = env!("DENO_GIT_COMMIT");
+
+#[cfg(test)]
+static V8_VERSION_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+  // The version can't change while the process runs, so only cross into
+  // libdeno once.
+  static ref V8_VERSION: String = {
+    #[cfg(test)]
+    V8_VERSION_CALLS.fetch_add(1, Ordering::SeqCst);
+    unsafe { string_from_c(libdeno::deno_v8_version()) }
+  };
+}
 
 pub fn v8() -> &'static str {
-  let version = unsafe { libdeno::deno_v8_version() };
-  let c_str = unsafe { CStr::from_ptr(version) };
-  c_str.to_str().unwrap()
+  &V8_VERSION
+}
+
+// Replaces invalid UTF-8, so a malformed string from libdeno can't panic.
+unsafe fn string_from_c(ptr: *const c_char) -> String {
+  CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+/// Returns true if this build of deno is version `major.minor.patch` or
+/// newer. Builds that are versioned 0.0.0, or not parseable, are development
+/// builds and count as newer than every release.
+pub fn is_at_least(major: u64, minor: u64, patch: u64) -> bool {
+  version_is_at_least(DENO, (major, minor, patch))
+}
+
+fn version_is_at_least(version: &str, wanted: (u64, u64, u64)) -> bool {
+  match parse(version) {
+    None | Some((0, 0, 0)) => true,
+    Some(v) => v >= wanted,
+  }
+}
+
+// Parses "major.minor.patch", ignoring any "-pre" or "+build" suffix.
+fn parse(version: &str) -> Option<(u64, u64, u64)> {
+  let core = version.split(|c| c == '-' || c == '+').next()?;
+  let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+  let major = parts.next()??;
+  let minor = parts.next()??;
+  let patch = parts.next()??;
+  if parts.next().is_some() {
+    return None;
+  }
+  Some((major, minor, patch))
+}
+
+/// The versions of deno and the engines it is built on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Versions {
+  pub deno: &'static str,
+  pub git_commit: &'static str,
+  pub v8: &'static str,
+}
+
+pub fn get() -> Versions {
+  Versions {
+    deno: DENO,
+    git_commit: GIT_COMMIT,
+    v8: v8(),
+  }
+}
+
+#[test]
+fn test_v8_version_is_cached() {
+  let a = v8();
+  let b = v8();
+  assert!(!a.is_empty());
+  assert_eq!(a.as_ptr(), b.as_ptr());
+  assert_eq!(V8_VERSION_CALLS.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_string_from_c_invalid_utf8() {
+  let bytes = b"7.4.\xff\0";
+  let s = unsafe { string_from_c(bytes.as_ptr() as *const c_char) };
+  assert_eq!(s, "7.4.\u{FFFD}");
+}
+
+#[test]
+fn test_get() {
+  let versions = get();
+  assert_eq!(versions.deno, DENO);
+  assert_eq!(versions.v8, v8());
+}
+
+#[test]
+fn test_git_commit() {
+  assert!(!GIT_COMMIT.is_empty());
+  assert_eq!(get().git_commit, GIT_COMMIT);
+}
+
+#[test]
+fn test_version_is_at_least() {
+  assert!(version_is_at_least("0.3.1", (0, 3, 1)));
+  assert!(version_is_at_least("0.3.1", (0, 2, 9)));
+  assert!(version_is_at_least("1.0.0", (0, 30, 0)));
+  assert!(!version_is_at_least("0.3.1", (0, 3, 2)));
+  assert!(!version_is_at_least("0.3.1", (1, 0, 0)));
+  assert!(version_is_at_least("0.4.0-rc1", (0, 4, 0)));
+  assert!(version_is_at_least("0.0.0", (99, 0, 0)));
+  assert!(version_is_at_least("not a version", (99, 0, 0)));
+  assert!(is_at_least(0, 0, 0));
 }
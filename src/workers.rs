@@ -65,7 +65,7 @@ pub fn spawn(
     .spawn(move || {
       let (worker, external_channels) = Worker::new(&state);
 
-      let resource = resources::add_worker(external_channels);
+      let resource = resources::add_worker(external_channels).unwrap();
       p.send(resource.clone()).unwrap();
 
       tokio_util::init(|| {